@@ -0,0 +1,110 @@
+// compares `minigrep::search`, which scans the whole input at once, with splitting the input into
+// lines first and checking each one with `str::find`, the way `search` used to work
+//
+// the input is a few hundred MB of made up log lines (256 by default, or however many are set in
+// MINIGREP_BENCH_MB), so this takes a little while to run:
+//
+//     cargo bench -p minigrep
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minigrep::{lines, search};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const WORDS: &[&str] = &[
+    "the", "request", "from", "client", "was", "handled", "in", "ms", "user", "session", "opened",
+    "closed", "for", "cache", "miss", "hit", "on", "key", "worker", "started", "stopped", "queue",
+    "length", "is", "now", "retrying", "after", "timeout", "with", "status", "ok", "error", "warn",
+    "debug", "info", "database", "query", "took", "rows", "returned",
+];
+
+// what's searched for, from found on most lines to not found at all
+const QUERIES: &[(&str, &str)] = &[
+    ("common", "the"),
+    ("phrase", "connection reset by peer"),
+    ("rare", "segfault"),
+    ("missing", "zyzzyva"),
+];
+
+fn main() {
+    let megabytes: usize = env::var("MINIGREP_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(256);
+    let contents = generate(megabytes * 1024 * 1024);
+    println!("searching {} MB of text\n", megabytes);
+
+    for &(name, query) in QUERIES {
+        let (expected, line_by_line_time) = best_of(3, || line_by_line(query, &contents));
+        let (found, search_time) = best_of(3, || search(query, &contents).len());
+        assert_eq!(
+            found, expected,
+            "search and line by line disagree on {:?}",
+            query
+        );
+
+        println!(
+            "{:<8} {:>9} matching lines   line by line {:>7.0} MB/s   search {:>7.0} MB/s   {:.1}x",
+            name,
+            found,
+            throughput(megabytes, line_by_line_time),
+            throughput(megabytes, search_time),
+            line_by_line_time.as_secs_f64() / search_time.as_secs_f64(),
+        );
+    }
+}
+
+// makes `len` or so bytes of lines of random words. the phrase and rare queries are sprinkled in
+// every so often
+fn generate(len: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(15);
+    let mut contents = String::with_capacity(len + 256);
+
+    while contents.len() < len {
+        for i in 0..rng.gen_range(4..16) {
+            if i > 0 {
+                contents.push(' ');
+            }
+            contents.push_str(WORDS[rng.gen_range(0..WORDS.len())]);
+        }
+        if rng.gen_ratio(1, 1_000) {
+            contents.push_str(" connection reset by peer");
+        }
+        if rng.gen_ratio(1, 100_000) {
+            contents.push_str(" segfault");
+        }
+        contents.push('\n');
+    }
+
+    contents
+}
+
+// how many lines have `query` in them, found by checking each line on its own
+fn line_by_line(query: &str, contents: &str) -> usize {
+    lines(contents)
+        .filter(|(_, line)| line.find(query).is_some())
+        .count()
+}
+
+// runs `f` `runs` times, giving back what it returned along with the fastest time it took
+fn best_of<T, F: FnMut() -> T>(runs: usize, mut f: F) -> (T, Duration) {
+    let mut best = None;
+    let mut result = None;
+
+    for _ in 0..runs {
+        let start = Instant::now();
+        let value = black_box(f());
+        let elapsed = start.elapsed();
+
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+        result = Some(value);
+    }
+
+    (result.unwrap(), best.unwrap())
+}
+
+fn throughput(megabytes: usize, time: Duration) -> f64 {
+    megabytes as f64 / time.as_secs_f64()
+}
@@ -0,0 +1,1180 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+use aho_corasick::AhoCorasick;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use regex::Regex;
+
+use crate::config_file::ConfigFile;
+use crate::error::Error;
+use crate::fold::has_uppercase;
+use crate::fuzzy::FuzzyQuery;
+use crate::matcher::Boundary;
+use crate::walk::{glob_set, Filter, STDIN};
+use crate::{compile_keywords, compile_literals, compile_regex, multiline_regex};
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH...]
+       minigrep [OPTIONS] -e QUERY... [PATH...]
+       minigrep [OPTIONS] -f FILE [PATH...]
+
+Prints the lines in each PATH that contain QUERY. Directories are searched recursively, and
+standard input is read when PATH is `-` or there isn't one. UTF-16 files are searched as text as
+long as they start with a byte order mark.
+
+Options that should always be on can go in a config file, one to a line (`--smart-case`, `-C 2`,
+`--color=never`), with `#` starting a comment. It's read from $MINIGREP_CONFIG if that's set, or
+from $XDG_CONFIG_HOME/minigrep/config (~/.config/minigrep/config) otherwise. An option that takes
+a value can be given a different one on the command line, but a switch like `-n`, `-v` or
+`--hidden` that's in the file can't be turned off again without --no-config. What to search for
+(`-e`, `-f`) and what to do with it (`-r`, `--in-place`, `--dry-run`, `--follow`) can only be
+given on the command line.
+
+Options:
+  -e, --pattern QUERY        search for QUERY, can be given more than once to search for any of
+                             them
+  -f, --pattern-file FILE    search for every line in FILE
+  -i, --ignore-case          ignore case when matching (the default if CASE_INSENSITIVE is set)
+  -s, --case-sensitive       match case exactly (the default otherwise)
+  -S, --smart-case           ignore case unless a QUERY has an uppercase letter in it
+  -E, --regex                treat QUERY as a regular expression
+  -w, --word-regexp          only match whole words, so `id` doesn't match in `width`
+  -x, --line-regexp          only match whole lines
+  -U, --multiline            let matches run over more than one line, printing every line a
+                             match is on. a plain QUERY can have `\\n` in it for a line break, and
+                             in a regex `.` matches line breaks too. each file is read into
+                             memory whole to search it
+  -r, --replace TEXT         print TEXT in place of each match. in regex mode, `$1`, `$name` and
+                             `${name}` in TEXT are replaced with what that capture group matched
+      --in-place             replace the matches in the files themselves instead of printing them
+      --dry-run              with --in-place, print a diff of what would change instead
+      --fuzzy K              match anything within K edits (chars added, removed or changed) of
+                             QUERY
+  -v, --invert-match         print the lines that don't match instead
+  -c, --count                only print how many lines matched in each file
+  -l, --files-with-matches   only print the names of files with a match
+  -L, --files-without-match  only print the names of files without a match
+  -m, --max-count N          stop reading a file after N matching lines
+      --json                 print each match as a JSON object on its own line, followed by a
+                             summary of the whole search
+  -n, --line-number          print the line number of each line
+  -b, --byte-offset          print the byte offset of each line in its file
+  -A, --after-context N      print N lines of context after each match
+  -B, --before-context N     print N lines of context before each match
+  -C, --context N            print N lines of context before and after each match
+      --include GLOB         only search files matching GLOB when searching a directory, can be
+                             given more than once
+      --exclude GLOB         skip files and directories matching GLOB when searching a directory,
+                             can be given more than once
+      --hidden               search files and directories whose names start with a `.`
+      --no-ignore            don't skip what .gitignore and .ignore files say to skip
+  -z, --decompress           search the contents of files compressed with gzip, bzip2 or zstd,
+                             whichever the start of each file says it is
+      --follow               keep watching each file after searching it and search new lines as
+                             they're added, like `tail -f`, even if the file is rotated or
+                             truncated
+      --no-mmap              always read files through a buffer, instead of memory mapping the
+                             big ones
+      --binary               search binary files (ones with a NUL byte near the start) too, but
+                             only print whether each one matches. they're skipped otherwise
+  -a, --text                 search binary files as if they were text
+  -j, --threads N            search N files at once (defaults to the number of cores)
+      --color WHEN           highlight matches, paths and line numbers: `auto` (the default)
+                             only does it when printing to a terminal and NO_COLOR isn't set,
+                             `always` and `never` do what they say
+      --no-config            don't read the config file
+      --seed N               when case sensitivity is picked at random (by Config::new_random),
+                             pick it the same way every time for the same N
+  -h, --help                 print this help and exit
+  -V, --version              print the version and exit";
+
+pub const VERSION: &str = concat!("minigrep ", env!("CARGO_PKG_VERSION"));
+
+// what the command line asked for. asking for help or the version doesn't need a query or paths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Search,
+    Help,
+    Version,
+}
+
+// whether output is colored (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    // only when printing to a terminal, and NO_COLOR isn't set. `run` works out which of the
+    // others this turns into before anything is printed
+    Auto,
+    Always,
+    Never,
+}
+
+// what to do with a file that looks like binary, which is one with a NUL byte near the start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+    // skip it, saying so on stderr (the default)
+    Skip,
+    // search it, but only print whether it matched instead of the lines that did (`--binary`)
+    Matches,
+    // search it like any other file (`-a`)
+    Text,
+}
+
+// what gets printed for each file that's searched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // the lines that matched (the default)
+    Lines,
+    // how many lines matched (`-c`)
+    Count,
+    // just the file's path, if anything matched (`-l`)
+    FilesWithMatches,
+    // just the file's path, if nothing matched (`-L`)
+    FilesWithoutMatch,
+    // a JSON object for each line that matched, and a summary at the end (`--json`)
+    Json,
+}
+
+pub struct Config {
+    pub action: Action,
+    // what to search for. a line matches if it has any of them in it
+    pub patterns: Vec<String>,
+    // files and directories to search, directories are searched recursively
+    pub paths: Vec<String>,
+    pub case_sensitive: bool,
+    // the compiled patterns when running in regex mode (`-E`/`--regex`), or when looking for more
+    // than one plain pattern while ignoring case. it's compiled once here so `run` doesn't have to
+    // redo it for every file it searches
+    pub regex: Option<Regex>,
+    // the compiled patterns when looking for any number of plain patterns but one while matching
+    // case, so every pattern is looked for in one pass over each line
+    pub keywords: Option<AhoCorasick>,
+    // the query when matching approximately (`--fuzzy K`)
+    pub fuzzy: Option<FuzzyQuery>,
+    // whether matches have to be whole words (`-w`) or whole lines (`-x`). a regex has this built
+    // into it already
+    pub boundary: Boundary,
+    // whether to search each file as a whole instead of line by line, so a match can run over
+    // more than one line (`-U`)
+    pub multiline: bool,
+    pub mode: Mode,
+    // what to swap each match for (`-r TEXT`)
+    pub replace: Option<String>,
+    // whether to rewrite files with the replacements made instead of printing lines
+    // (`--in-place`), and whether to only print what would change (`--dry-run`)
+    pub in_place: bool,
+    pub dry_run: bool,
+    // look for the lines that *don't* match (`-v`)
+    pub invert_match: bool,
+    // stop reading a file after this many matching lines (`-m N`)
+    pub max_count: Option<usize>,
+    // print the line number (`-n`) and/or the byte offset of the line in its file (`-b`) before
+    // each line
+    pub line_number: bool,
+    pub byte_offset: bool,
+    // how many lines of context to print before (`-B N`) and after (`-A N`) each match. `-C N`
+    // sets both
+    pub before_context: usize,
+    pub after_context: usize,
+    // which files to search when walking a directory
+    pub filter: Filter,
+    // whether to decompress compressed files before searching them (`-z`)
+    pub decompress: bool,
+    // whether to keep watching files for new lines once they've been searched (`--follow`)
+    pub follow: bool,
+    // whether big files can be memory mapped instead of read (turned off with `--no-mmap`)
+    pub mmap: bool,
+    pub binary: Binary,
+    // how many files to search at the same time (`-j N`)
+    pub threads: usize,
+    pub color: Color,
+}
+
+impl Config {
+    // parses the command line `args` (including the program name) into a Config. environment
+    // variables and the config file only fill in defaults, which flags on the command line
+    // override where they can
+    pub fn new<I>(args: I) -> Result<Config, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let args: Vec<String> = args.into_iter().collect();
+        let file = config_file(&args)?;
+        Config::build(file.as_ref(), args, |_| {
+            env::var("CASE_INSENSITIVE").is_err()
+        })
+    }
+
+    // same as `new`, but flips a coin for case sensitivity when the flags don't pick one
+    pub fn new_random<I>(args: I) -> Result<Config, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        Config::new_random_with(args, &mut thread_rng())
+    }
+
+    // same as `new_random`, but the coin is flipped with `rng`, so a seeded one always picks the
+    // same way. `--seed N` flips it with an rng seeded with N instead, and `rng` isn't used at all
+    // when the flags pick the case sensitivity themselves
+    pub fn new_random_with<I, R>(args: I, rng: &mut R) -> Result<Config, Error>
+    where
+        I: IntoIterator<Item = String>,
+        R: Rng + ?Sized,
+    {
+        let args: Vec<String> = args.into_iter().collect();
+        let file = config_file(&args)?;
+        Config::build(file.as_ref(), args, |seed| match seed {
+            Some(seed) => StdRng::seed_from_u64(seed).gen_bool(0.5),
+            None => rng.gen_bool(0.5),
+        })
+    }
+
+    // parses `args` (without the program name) like `new` does, but without looking at the config
+    // file or the environment, so tests don't depend on whoever's running them
+    #[cfg(test)]
+    pub fn for_test(args: &[&str]) -> Result<Config, Error> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        Config::build(None, args, |_| true)
+    }
+
+    // shared by the constructors above, which only differ in how they pick the case sensitivity
+    // when the flags don't. that's only worked out once the flags have been parsed, and is handed
+    // the `--seed` if there was one. the flags in `file` are parsed first, so the ones in `args`
+    // override them
+    fn build<I, F>(file: Option<&ConfigFile>, args: I, case_sensitive: F) -> Result<Config, Error>
+    where
+        I: IntoIterator<Item = String>,
+        F: FnOnce(Option<u64>) -> bool,
+    {
+        let mut parser = Parser {
+            config: Config {
+                action: Action::Search,
+                patterns: Vec::new(),
+                paths: Vec::new(),
+                // set once the flags have been parsed
+                case_sensitive: true,
+                regex: None,
+                keywords: None,
+                fuzzy: None,
+                boundary: Boundary::Any,
+                multiline: false,
+                mode: Mode::Lines,
+                replace: None,
+                in_place: false,
+                dry_run: false,
+                invert_match: false,
+                max_count: None,
+                line_number: false,
+                byte_offset: false,
+                before_context: 0,
+                after_context: 0,
+                filter: Filter::default(),
+                decompress: false,
+                follow: false,
+                mmap: true,
+                binary: Binary::Skip,
+                threads: thread::available_parallelism().map_or(1, |n| n.get()),
+                color: Color::Auto,
+            },
+            use_regex: false,
+            case_sensitive: None,
+            smart_case: false,
+            seed: None,
+            patterns: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            fuzzy: None,
+            positional: Vec::new(),
+            in_config_file: false,
+        };
+
+        if let Some(file) = file {
+            parser.in_config_file = true;
+            for (line, flags) in &file.lines {
+                let in_file = |e| Error::ConfigFile {
+                    path: file.path.clone(),
+                    line: *line,
+                    source: Box::new(e),
+                };
+                parser.parse(&mut flags.iter().cloned()).map_err(in_file)?;
+                // anything that isn't a flag would be taken as the query or a path
+                if let Some(arg) = parser.positional.pop() {
+                    return Err(in_file(Error::NotAFlag(arg)));
+                }
+            }
+        }
+
+        parser.in_config_file = false;
+
+        // skip first arg (just name of program)
+        let mut args = args.into_iter().skip(1);
+        parser.parse(&mut args)?;
+
+        let mut config = parser.config;
+        if config.action != Action::Search {
+            return Ok(config);
+        }
+        config.case_sensitive = match parser.case_sensitive {
+            Some(case_sensitive) => case_sensitive,
+            None => case_sensitive(parser.seed),
+        };
+
+        // whatever wasn't a flag is the query followed by the paths, unless the query was given
+        // with `-e`/`-f`
+        let mut positional = parser.positional.into_iter();
+        config.patterns = match parser.patterns {
+            Some(patterns) => patterns,
+            None => match positional.next() {
+                Some(arg) => vec![arg],
+                None => return Err(Error::MissingQuery),
+            },
+        };
+        // read from stdin if there's nowhere else to read from
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            config.paths.push(String::from(STDIN));
+        }
+
+        config.filter.include = glob_set(&parser.include)?;
+        config.filter.exclude = glob_set(&parser.exclude)?;
+
+        let use_regex = parser.use_regex;
+        if parser.smart_case {
+            config.case_sensitive = config
+                .patterns
+                .iter()
+                .any(|pattern| has_uppercase(pattern, use_regex));
+        }
+        // there's no easy way to type a line break into a plain query, so `\n` stands for one. a
+        // regex understands `\n` already
+        if config.multiline && !use_regex {
+            for pattern in &mut config.patterns {
+                *pattern = pattern.replace("\\n", "\n");
+            }
+        }
+        let patterns = &config.patterns;
+        if let Some(max_errors) = parser.fuzzy {
+            if use_regex || patterns.len() != 1 {
+                return Err(Error::Conflict(
+                    "--fuzzy only works with a single QUERY, and not with -E",
+                ));
+            }
+            let fuzzy = FuzzyQuery::new(&patterns[0], max_errors, config.case_sensitive)?;
+            config.fuzzy = Some(fuzzy);
+        } else if use_regex && !patterns.is_empty() {
+            let pattern = match patterns.as_slice() {
+                [pattern] => pattern.clone(),
+                _ => {
+                    let alternation: Vec<String> =
+                        patterns.iter().map(|p| format!("(?:{})", p)).collect();
+                    alternation.join("|")
+                }
+            };
+            let mut pattern = config.boundary.wrap(&pattern);
+            if config.multiline {
+                pattern = multiline_regex(&pattern);
+            }
+            config.regex = Some(compile_regex(&pattern, config.case_sensitive)?);
+        } else if patterns.len() > 1 && (!config.case_sensitive || config.boundary != Boundary::Any)
+        {
+            // the regex can check the boundary for each pattern, where Aho-Corasick only gives
+            // back one match at each place and that one might not be a whole word
+            config.regex = Some(compile_literals(
+                patterns,
+                config.case_sensitive,
+                config.boundary,
+                config.multiline,
+            )?);
+        } else if patterns.len() != 1 {
+            // (no patterns at all, from an empty `-f` file, ends up here too and matches nothing)
+            config.keywords = Some(compile_keywords(patterns)?);
+        }
+
+        // capture group references get filled in whenever there's a regex, so when the regex is
+        // only there to find plain patterns, a `$` in the replacement has to stay a `$`
+        if !use_regex && config.regex.is_some() {
+            config.replace = config.replace.map(|replace| replace.replace('$', "$$"));
+        }
+        if config.in_place {
+            if config.replace.is_none() {
+                return Err(Error::Conflict("--in-place needs -r/--replace"));
+            }
+            if config.invert_match || config.mode != Mode::Lines {
+                return Err(Error::Conflict(
+                    "--in-place can't be used with -v, -c, -l, -L or --json",
+                ));
+            }
+            if config.paths.iter().any(|path| path == STDIN) {
+                return Err(Error::Conflict("--in-place can't edit standard input"));
+            }
+        } else if config.dry_run {
+            return Err(Error::Conflict("--dry-run needs --in-place"));
+        }
+
+        if config.follow {
+            if config.in_place
+                || config.decompress
+                || !matches!(config.mode, Mode::Lines | Mode::Json)
+            {
+                return Err(Error::Conflict(
+                    "--follow can't be used with --in-place, -z, -c, -l or -L",
+                ));
+            }
+            if config.paths.iter().any(|path| path == STDIN) {
+                return Err(Error::Conflict(
+                    "--follow needs files to follow, not standard input",
+                ));
+            }
+        }
+
+        // replacing is done a line at a time, and following only ever reads part of a file
+        if config.multiline && (config.replace.is_some() || config.follow) {
+            return Err(Error::Conflict(
+                "-U can't be used with -r, --in-place or --follow",
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+// every flag minigrep understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flag {
+    IgnoreCase,
+    CaseSensitive,
+    SmartCase,
+    Pattern,
+    PatternFile,
+    Regex,
+    WordRegexp,
+    LineRegexp,
+    Multiline,
+    Fuzzy,
+    InvertMatch,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    MaxCount,
+    Json,
+    Replace,
+    InPlace,
+    DryRun,
+    LineNumber,
+    ByteOffset,
+    AfterContext,
+    BeforeContext,
+    Context,
+    Include,
+    Exclude,
+    Hidden,
+    NoIgnore,
+    Decompress,
+    Follow,
+    NoMmap,
+    Binary,
+    Text,
+    NoConfig,
+    Seed,
+    Threads,
+    Color,
+    Help,
+    Version,
+}
+
+// the short and long name each flag goes by on the command line. not every flag has a short name
+const FLAGS: &[(Option<char>, &str, Flag)] = &[
+    (Some('i'), "ignore-case", Flag::IgnoreCase),
+    (Some('s'), "case-sensitive", Flag::CaseSensitive),
+    (Some('S'), "smart-case", Flag::SmartCase),
+    (Some('e'), "pattern", Flag::Pattern),
+    (Some('f'), "pattern-file", Flag::PatternFile),
+    (Some('E'), "regex", Flag::Regex),
+    (Some('w'), "word-regexp", Flag::WordRegexp),
+    (Some('x'), "line-regexp", Flag::LineRegexp),
+    (Some('U'), "multiline", Flag::Multiline),
+    (None, "fuzzy", Flag::Fuzzy),
+    (Some('v'), "invert-match", Flag::InvertMatch),
+    (Some('c'), "count", Flag::Count),
+    (Some('l'), "files-with-matches", Flag::FilesWithMatches),
+    (Some('L'), "files-without-match", Flag::FilesWithoutMatch),
+    (Some('m'), "max-count", Flag::MaxCount),
+    (None, "json", Flag::Json),
+    (Some('r'), "replace", Flag::Replace),
+    (None, "in-place", Flag::InPlace),
+    (None, "dry-run", Flag::DryRun),
+    (Some('n'), "line-number", Flag::LineNumber),
+    (Some('b'), "byte-offset", Flag::ByteOffset),
+    (Some('A'), "after-context", Flag::AfterContext),
+    (Some('B'), "before-context", Flag::BeforeContext),
+    (Some('C'), "context", Flag::Context),
+    (None, "include", Flag::Include),
+    (None, "exclude", Flag::Exclude),
+    (None, "hidden", Flag::Hidden),
+    (None, "no-ignore", Flag::NoIgnore),
+    (Some('z'), "decompress", Flag::Decompress),
+    (None, "follow", Flag::Follow),
+    (None, "no-mmap", Flag::NoMmap),
+    (None, "binary", Flag::Binary),
+    (Some('a'), "text", Flag::Text),
+    (None, "no-config", Flag::NoConfig),
+    (None, "seed", Flag::Seed),
+    (Some('j'), "threads", Flag::Threads),
+    (None, "color", Flag::Color),
+    (Some('h'), "help", Flag::Help),
+    (Some('V'), "version", Flag::Version),
+];
+
+impl Flag {
+    fn from_short(c: char) -> Result<Flag, Error> {
+        FLAGS
+            .iter()
+            .find(|(short, _, _)| *short == Some(c))
+            .map(|(_, _, flag)| *flag)
+            .ok_or_else(|| Error::UnknownFlag(format!("-{}", c)))
+    }
+
+    fn from_long(name: &str) -> Result<Flag, Error> {
+        FLAGS
+            .iter()
+            .find(|(_, long, _)| *long == name)
+            .map(|(_, _, flag)| *flag)
+            .ok_or_else(|| Error::UnknownFlag(format!("--{}", name)))
+    }
+
+    fn takes_value(self) -> bool {
+        matches!(
+            self,
+            Flag::Pattern
+                | Flag::PatternFile
+                | Flag::Fuzzy
+                | Flag::MaxCount
+                | Flag::Replace
+                | Flag::AfterContext
+                | Flag::BeforeContext
+                | Flag::Context
+                | Flag::Include
+                | Flag::Exclude
+                | Flag::Seed
+                | Flag::Threads
+                | Flag::Color
+        )
+    }
+
+    // the flags that say what to search for or what to do with the matches only make sense for
+    // one search. a `-e` in the config file would also quietly turn the QUERY into a path
+    fn allowed_in_config_file(self) -> bool {
+        !matches!(
+            self,
+            Flag::Pattern
+                | Flag::PatternFile
+                | Flag::Replace
+                | Flag::InPlace
+                | Flag::DryRun
+                | Flag::Follow
+                | Flag::NoConfig
+                | Flag::Help
+                | Flag::Version
+        )
+    }
+
+    fn name(self) -> String {
+        let (short, long, _) = FLAGS.iter().find(|(_, _, flag)| *flag == self).unwrap();
+        match short {
+            Some(short) => format!("-{}/--{}", short, long),
+            None => format!("--{}", long),
+        }
+    }
+}
+
+// holds on to everything that's been parsed so far
+struct Parser {
+    config: Config,
+    use_regex: bool,
+    // whether `-i` or `-s` picked the case sensitivity, instead of leaving it to the default
+    case_sensitive: Option<bool>,
+    // smart case can only be worked out once the query is known
+    smart_case: bool,
+    // what to seed the rng that picks the default case sensitivity with (`--seed N`)
+    seed: Option<u64>,
+    // the patterns given with `-e` and `-f`, if there were any. otherwise the query is the first
+    // positional arg
+    patterns: Option<Vec<String>>,
+    // how many errors `--fuzzy` allows. the query is built once everything else about it is known
+    fuzzy: Option<usize>,
+    // globs for `--include` and `--exclude`, compiled once they've all been seen
+    include: Vec<String>,
+    exclude: Vec<String>,
+    positional: Vec<String>,
+    // whether the flags being parsed are from the config file
+    in_config_file: bool,
+}
+
+impl Parser {
+    fn parse<I>(&mut self, args: &mut I) -> Result<(), Error>
+    where
+        I: Iterator<Item = String>,
+    {
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                // everything after `--` is positional, even if it looks like a flag
+                self.positional.extend(args.by_ref());
+            } else if let Some(long) = arg.strip_prefix("--") {
+                // `--name` or `--name=value`
+                let (name, value) = match long.find('=') {
+                    Some(i) => (&long[..i], Some(long[i + 1..].to_string())),
+                    None => (long, None),
+                };
+                let flag = Flag::from_long(name)?;
+                let value = match value {
+                    Some(value) if flag.takes_value() => Some(value),
+                    Some(_) => return Err(Error::UnexpectedValue { flag: flag.name() }),
+                    None if flag.takes_value() => args.next(),
+                    None => None,
+                };
+                self.apply(flag, value)?;
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // a cluster of short flags like `-in`. a flag that takes a value uses up the rest
+                // of the cluster (`-A3`), or the next arg if there's nothing left (`-A 3`)
+                for (i, c) in arg.char_indices().skip(1) {
+                    let flag = Flag::from_short(c)?;
+                    if flag.takes_value() {
+                        let rest = &arg[i + c.len_utf8()..];
+                        let value = if rest.is_empty() {
+                            args.next()
+                        } else {
+                            Some(rest.to_string())
+                        };
+                        self.apply(flag, value)?;
+                        break;
+                    }
+                    self.apply(flag, None)?;
+                }
+            } else {
+                self.positional.push(arg);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, flag: Flag, value: Option<String>) -> Result<(), Error> {
+        if self.in_config_file && !flag.allowed_in_config_file() {
+            return Err(Error::NotForConfigFile { flag: flag.name() });
+        }
+        let config = &mut self.config;
+        match flag {
+            // whichever of these comes last wins
+            Flag::IgnoreCase => {
+                self.case_sensitive = Some(false);
+                self.smart_case = false;
+            }
+            Flag::CaseSensitive => {
+                self.case_sensitive = Some(true);
+                self.smart_case = false;
+            }
+            Flag::SmartCase => self.smart_case = true,
+            Flag::Pattern => {
+                let pattern = required(flag, value, "a pattern")?;
+                self.patterns.get_or_insert_with(Vec::new).push(pattern);
+            }
+            Flag::PatternFile => {
+                let path = PathBuf::from(required(flag, value, "a file")?);
+                let contents =
+                    fs::read_to_string(&path).map_err(|source| Error::Io { path, source })?;
+                self.patterns
+                    .get_or_insert_with(Vec::new)
+                    .extend(contents.lines().map(String::from));
+            }
+            Flag::Regex => self.use_regex = true,
+            // like grep, `-x` wins over `-w` whichever order they're given in
+            Flag::WordRegexp if config.boundary != Boundary::Line => {
+                config.boundary = Boundary::Word
+            }
+            Flag::WordRegexp => {}
+            Flag::LineRegexp => config.boundary = Boundary::Line,
+            Flag::Multiline => config.multiline = true,
+            Flag::Fuzzy => self.fuzzy = Some(number_arg(flag, value)?),
+            Flag::InvertMatch => config.invert_match = true,
+            Flag::Count => config.mode = Mode::Count,
+            Flag::FilesWithMatches => config.mode = Mode::FilesWithMatches,
+            Flag::FilesWithoutMatch => config.mode = Mode::FilesWithoutMatch,
+            Flag::MaxCount => config.max_count = Some(number_arg(flag, value)?),
+            Flag::Json => config.mode = Mode::Json,
+            Flag::Replace => config.replace = Some(required(flag, value, "some text")?),
+            Flag::InPlace => config.in_place = true,
+            Flag::DryRun => config.dry_run = true,
+            Flag::LineNumber => config.line_number = true,
+            Flag::ByteOffset => config.byte_offset = true,
+            Flag::AfterContext => config.after_context = number_arg(flag, value)?,
+            Flag::BeforeContext => config.before_context = number_arg(flag, value)?,
+            Flag::Context => {
+                config.after_context = number_arg(flag, value)?;
+                config.before_context = config.after_context;
+            }
+            Flag::Include | Flag::Exclude => {
+                let glob = required(flag, value, "a glob")?;
+                if flag == Flag::Include {
+                    self.include.push(glob);
+                } else {
+                    self.exclude.push(glob);
+                }
+            }
+            Flag::Hidden => config.filter.hidden = true,
+            Flag::NoIgnore => config.filter.ignore_files = false,
+            Flag::Decompress => config.decompress = true,
+            Flag::Follow => config.follow = true,
+            Flag::NoMmap => config.mmap = false,
+            // whichever of these comes last wins
+            Flag::Binary => config.binary = Binary::Matches,
+            Flag::Text => config.binary = Binary::Text,
+            // whether to read the config file has to be known before any flags are parsed, so
+            // this is taken care of by `config_file`
+            Flag::NoConfig => {}
+            Flag::Seed => self.seed = Some(number_arg(flag, value)?),
+            Flag::Threads => {
+                config.threads = number_arg(flag, value)?;
+                if config.threads == 0 {
+                    return Err(Error::InvalidValue {
+                        flag: flag.name(),
+                        value: String::from("0"),
+                        expected: "at least 1",
+                    });
+                }
+            }
+            Flag::Color => {
+                let expected = "`auto`, `always` or `never`";
+                config.color = match required(flag, value, expected)?.as_str() {
+                    "auto" => Color::Auto,
+                    "always" => Color::Always,
+                    "never" => Color::Never,
+                    value => {
+                        return Err(Error::InvalidValue {
+                            flag: flag.name(),
+                            value: value.to_string(),
+                            expected,
+                        })
+                    }
+                }
+            }
+            Flag::Help => config.action = Action::Help,
+            Flag::Version => config.action = Action::Version,
+        }
+
+        Ok(())
+    }
+}
+
+// reads the config file, unless `args` has `--no-config` in it
+fn config_file(args: &[String]) -> Result<Option<ConfigFile>, Error> {
+    if args
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--no-config")
+    {
+        return Ok(None);
+    }
+    ConfigFile::load()
+}
+
+// gives back the value given to `flag`, or says it needs `expected` when there wasn't one
+fn required(flag: Flag, value: Option<String>, expected: &'static str) -> Result<String, Error> {
+    value.ok_or_else(|| Error::MissingValue {
+        flag: flag.name(),
+        expected,
+    })
+}
+
+// parses the number given to a flag like `-A`
+fn number_arg<T: FromStr>(flag: Flag, value: Option<String>) -> Result<T, Error> {
+    let value = required(flag, value, "a number")?;
+    value.parse().map_err(|_| Error::InvalidValue {
+        flag: flag.name(),
+        value,
+        expected: "a number",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Config, Error> {
+        Config::for_test(args)
+    }
+
+    // what went wrong parsing `args`, the way it'd be printed
+    fn error(args: &[&str]) -> String {
+        parse(args).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn positional() {
+        let config = parse(&["duct", "poem.txt", "src"]).unwrap();
+        assert_eq!(config.action, Action::Search);
+        assert_eq!(config.patterns, vec!["duct"]);
+        assert_eq!(config.paths, vec!["poem.txt", "src"]);
+        assert!(config.case_sensitive);
+        assert_eq!(config.mode, Mode::Lines);
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config = parse(&[
+            "-inz",
+            "--count",
+            "-A2",
+            "--before-context=3",
+            "duct",
+            "poem.txt",
+        ])
+        .unwrap();
+        assert!(!config.case_sensitive);
+        assert!(config.line_number);
+        assert!(config.decompress);
+        assert!(config.mmap);
+        assert_eq!(config.mode, Mode::Count);
+        assert_eq!(config.after_context, 2);
+        assert_eq!(config.before_context, 3);
+    }
+
+    #[test]
+    fn flags_override_defaults() {
+        let args = ["minigrep", "-s", "duct", "poem.txt"];
+        let config =
+            Config::build(None, args.iter().map(|arg| arg.to_string()), |_| false).unwrap();
+        assert!(config.case_sensitive);
+    }
+
+    #[test]
+    fn random_case() {
+        let new = |args: &[&str], rng: &mut StdRng| {
+            let args = ["minigrep", "--no-config"].iter().chain(args);
+            Config::new_random_with(args.map(|arg| arg.to_string()), rng)
+                .unwrap()
+                .case_sensitive
+        };
+
+        // the same seed always picks the same way, and different ones don't all pick the same
+        let picks: Vec<bool> = (0..16)
+            .map(|seed| new(&["duct"], &mut StdRng::seed_from_u64(seed)))
+            .collect();
+        for (seed, &pick) in (0..16).zip(&picks) {
+            assert_eq!(new(&["duct"], &mut StdRng::seed_from_u64(seed)), pick);
+        }
+        assert!(picks.contains(&true) && picks.contains(&false));
+
+        // `--seed` wins over the rng that's passed in
+        let seeded: Vec<bool> = (0..16)
+            .map(|seed| new(&["--seed", "7", "duct"], &mut StdRng::seed_from_u64(seed)))
+            .collect();
+        assert!(seeded.iter().all(|&pick| pick == seeded[0]));
+
+        // and the flags win over both
+        for seed in 0..16 {
+            let rng = &mut StdRng::seed_from_u64(seed);
+            assert!(new(&["-s", "--seed=7", "duct"], rng));
+            assert!(!new(&["-i", "duct"], rng));
+        }
+
+        assert_eq!(
+            error(&["--seed", "-1", "duct"]),
+            "invalid value `-1` for --seed, expected a number"
+        );
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(!parse(&["-S", "duct"]).unwrap().case_sensitive);
+        assert!(parse(&["-S", "Duct"]).unwrap().case_sensitive);
+        assert!(!parse(&["-SE", r"\Sduct"]).unwrap().case_sensitive);
+        assert!(parse(&["-S", "-s", "duct"]).unwrap().case_sensitive);
+        assert!(!parse(&["-s", "-S", "duct"]).unwrap().case_sensitive);
+    }
+
+    #[test]
+    fn flag_values_as_next_arg() {
+        let config = parse(&["-C", "1", "-vl", "duct", "poem.txt"]).unwrap();
+        assert_eq!((config.before_context, config.after_context), (1, 1));
+        assert!(config.invert_match);
+        assert_eq!(config.mode, Mode::FilesWithMatches);
+    }
+
+    #[test]
+    fn max_count_and_files_without_match() {
+        let config = parse(&["-Lm", "5", "duct"]).unwrap();
+        assert_eq!(config.mode, Mode::FilesWithoutMatch);
+        assert_eq!(config.max_count, Some(5));
+        assert_eq!(parse(&["duct"]).unwrap().max_count, None);
+    }
+
+    #[test]
+    fn long_only_flag() {
+        assert_eq!(parse(&["--json", "duct"]).unwrap().mode, Mode::Json);
+        assert_eq!(
+            error(&["--json=yes", "duct"]),
+            "--json doesn't take a value"
+        );
+    }
+
+    #[test]
+    fn threads() {
+        assert_eq!(parse(&["-j", "3", "duct"]).unwrap().threads, 3);
+        assert_eq!(
+            error(&["-j0", "duct"]),
+            "invalid value `0` for -j/--threads, expected at least 1"
+        );
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(parse(&["duct"]).unwrap().color, Color::Auto);
+        assert_eq!(
+            parse(&["--color=always", "duct"]).unwrap().color,
+            Color::Always
+        );
+        assert_eq!(
+            parse(&["--color", "never", "duct"]).unwrap().color,
+            Color::Never
+        );
+        assert!(parse(&["--color=sometimes", "duct"]).is_err());
+    }
+
+    #[test]
+    fn patterns() {
+        let config = parse(&["-e", "duct", "--pattern=tape", "poem.txt"]).unwrap();
+        assert_eq!(config.patterns, vec!["duct", "tape"]);
+        assert_eq!(config.paths, vec!["poem.txt"]);
+        assert!(config.keywords.is_some());
+
+        // ignoring case falls back to a regex so the folding works the same as with one pattern
+        let config = parse(&["-i", "-e", "duct", "-e", "tape"]).unwrap();
+        assert!(config.regex.is_some());
+
+        let config = parse(&["-E", "-e", "du.t", "-e", "ta+pe"]).unwrap();
+        assert!(config.regex.unwrap().is_match("taaape"));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("patterns.txt");
+        fs::write(&path, "duct\ntape\n").unwrap();
+        let config = parse(&["-e", "glue", "-f", path.to_str().unwrap(), "poem.txt"]).unwrap();
+        assert_eq!(config.patterns, vec!["glue", "duct", "tape"]);
+
+        assert!(parse(&["-f", "no/such/file"]).is_err());
+    }
+
+    #[test]
+    fn filter() {
+        let config = parse(&["duct"]).unwrap();
+        assert!(config.filter.include.is_none());
+        assert!(!config.filter.hidden);
+        assert!(config.filter.ignore_files);
+
+        let config = parse(&[
+            "--include=*.rs",
+            "--include",
+            "*.toml",
+            "--exclude=target",
+            "--hidden",
+            "--no-ignore",
+            "duct",
+        ])
+        .unwrap();
+        assert_eq!(config.filter.include.unwrap().len(), 2);
+        assert!(config.filter.exclude.unwrap().is_match("target"));
+        assert!(config.filter.hidden);
+        assert!(!config.filter.ignore_files);
+
+        assert!(parse(&["--include=a[", "duct"]).is_err());
+    }
+
+    #[test]
+    fn replace() {
+        let config = parse(&["-r", "$1", "duct"]).unwrap();
+        assert_eq!(config.replace.as_deref(), Some("$1"));
+        assert!(!config.in_place);
+
+        // a plain pattern that's searched for with a regex still gets its `$` taken literally
+        let config = parse(&["-i", "-e", "duct", "-e", "tape", "-r", "$1"]).unwrap();
+        assert_eq!(config.replace.as_deref(), Some("$$1"));
+        let config = parse(&["-E", "-e", "duct", "-e", "tape", "-r", "$1"]).unwrap();
+        assert_eq!(config.replace.as_deref(), Some("$1"));
+
+        let config = parse(&["--replace=glue", "--in-place", "--dry-run", "duct", "a"]).unwrap();
+        assert!(config.in_place && config.dry_run);
+
+        assert!(parse(&["--in-place", "duct"]).is_err());
+        assert!(parse(&["-r", "glue", "--in-place", "-c", "duct"]).is_err());
+        assert!(parse(&["-r", "glue", "--dry-run", "duct"]).is_err());
+    }
+
+    #[test]
+    fn boundary() {
+        assert_eq!(parse(&["duct"]).unwrap().boundary, Boundary::Any);
+        assert_eq!(parse(&["-w", "duct"]).unwrap().boundary, Boundary::Word);
+        assert_eq!(parse(&["-xw", "duct"]).unwrap().boundary, Boundary::Line);
+        assert_eq!(parse(&["-wx", "duct"]).unwrap().boundary, Boundary::Line);
+
+        // more than one plain pattern is found with a regex, which checks the boundary itself
+        let config = parse(&["-w", "-e", "duct", "-e", "duct tape"]).unwrap();
+        assert!(config.keywords.is_none());
+        let re = config.regex.unwrap();
+        assert_eq!(re.find("duct taped").map(|m| m.range()), Some(0..4));
+        let re = parse(&["-xE", "duct|tape"]).unwrap().regex.unwrap();
+        assert!(re.is_match("tape") && !re.is_match("duct tape"));
+    }
+
+    #[test]
+    fn multiline() {
+        let config = parse(&["-U", r"duct\ntape", "poem.txt"]).unwrap();
+        assert!(config.multiline);
+        assert_eq!(config.patterns, vec!["duct\ntape"]);
+        // a regex keeps its `\n` as it is
+        let config = parse(&["--multiline", "-E", r"duct.tape$"]).unwrap();
+        assert_eq!(config.patterns, vec![r"duct.tape$"]);
+        let re = config.regex.unwrap();
+        assert!(re.is_match("duct\ntape\r\nglue") && !re.is_match("duct\ntapes"));
+
+        let re = parse(&["-Ux", "-e", "duct", "-e", "tape"])
+            .unwrap()
+            .regex
+            .unwrap();
+        assert!(re.is_match("glue\r\ntape\r\n"));
+
+        assert_eq!(
+            error(&["-U", "-r", "glue", "duct"]),
+            "-U can't be used with -r, --in-place or --follow"
+        );
+    }
+
+    #[test]
+    fn binary() {
+        assert_eq!(parse(&["duct"]).unwrap().binary, Binary::Skip);
+        assert_eq!(
+            parse(&["--binary", "duct"]).unwrap().binary,
+            Binary::Matches
+        );
+        assert_eq!(parse(&["-a", "duct"]).unwrap().binary, Binary::Text);
+        assert_eq!(
+            parse(&["-a", "--binary", "duct"]).unwrap().binary,
+            Binary::Matches
+        );
+    }
+
+    #[test]
+    fn no_mmap() {
+        assert!(!parse(&["--no-mmap", "duct"]).unwrap().mmap);
+    }
+
+    #[test]
+    fn config_file() {
+        let file = ConfigFile::parse(
+            Path::new("minigrep.conf"),
+            "--smart-case\n# more context\n-C 2\n--color=never\n",
+        );
+        let build = |file: &ConfigFile, args: &[&str]| {
+            let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+            Config::build(Some(file), args, |_| true)
+        };
+
+        let config = build(&file, &["-A", "1", "duct"]).unwrap();
+        assert!(!config.case_sensitive);
+        assert_eq!((config.before_context, config.after_context), (2, 1));
+        assert_eq!(config.color, Color::Never);
+        // flags on the command line win over the ones in the file
+        assert!(build(&file, &["-s", "duct"]).unwrap().case_sensitive);
+
+        let bad_value = ConfigFile::parse(Path::new("minigrep.conf"), "-n\n\n-C lots\n");
+        assert_eq!(
+            build(&bad_value, &["duct"]).err().unwrap().to_string(),
+            "minigrep.conf:3: invalid value `lots` for -C/--context, expected a number"
+        );
+        let not_a_flag = ConfigFile::parse(Path::new("minigrep.conf"), "duct\n");
+        assert_eq!(
+            build(&not_a_flag, &["tape"]).err().unwrap().to_string(),
+            "minigrep.conf:1: expected a flag, found `duct`"
+        );
+        let pattern = ConfigFile::parse(Path::new("minigrep.conf"), "-n\n-e tape\n");
+        assert_eq!(
+            build(&pattern, &["duct"]).err().unwrap().to_string(),
+            "minigrep.conf:2: -e/--pattern can't be used in a config file"
+        );
+        let follow = ConfigFile::parse(Path::new("minigrep.conf"), "--follow\n");
+        assert!(build(&follow, &["duct", "app.log"]).is_err());
+    }
+
+    #[test]
+    fn follow() {
+        assert!(parse(&["--follow", "duct", "app.log"]).unwrap().follow);
+        assert!(matches!(
+            parse(&["--follow", "duct"]),
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            parse(&["--follow", "-c", "duct", "app.log"]),
+            Err(Error::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn fuzzy() {
+        let config = parse(&["--fuzzy", "1", "conection"]).unwrap();
+        assert!(config.fuzzy.is_some());
+        assert!(config.regex.is_none() && config.keywords.is_none());
+
+        assert!(parse(&["--fuzzy=1", "-E", "conection"]).is_err());
+        assert!(parse(&["--fuzzy=1", "-e", "a", "-e", "b"]).is_err());
+        assert!(parse(&["--fuzzy=many", "conection"]).is_err());
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = parse(&["--", "-n", "poem.txt"]).unwrap();
+        assert_eq!(config.patterns, vec!["-n"]);
+        assert!(!config.line_number);
+    }
+
+    #[test]
+    fn help_and_version_need_nothing_else() {
+        assert_eq!(parse(&["--help"]).unwrap().action, Action::Help);
+        assert_eq!(parse(&["-V"]).unwrap().action, Action::Version);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(&["-y", "duct", "poem.txt"]), "unknown flag `-y`");
+        assert_eq!(
+            error(&["-A", "lots", "duct", "poem.txt"]),
+            "invalid value `lots` for -A/--after-context, expected a number"
+        );
+        assert_eq!(
+            error(&["--count=2", "duct", "poem.txt"]),
+            "-c/--count doesn't take a value"
+        );
+        assert_eq!(error(&[]), "couldn't extract query");
+        assert_eq!(error(&["duct", "-A"]), "-A/--after-context needs a number");
+        assert!(matches!(parse(&["-y", "duct"]), Err(Error::UnknownFlag(flag)) if flag == "-y"));
+        assert!(matches!(
+            parse(&["-E", "req-("]),
+            Err(Error::Regex { pattern, .. }) if pattern == "req-("
+        ));
+        assert!(matches!(
+            parse(&["-f", "no/such/file"]),
+            Err(Error::Io { path, .. }) if path == Path::new("no/such/file")
+        ));
+        assert!(matches!(
+            parse(&["-r", "glue", "--in-place", "duct", "-"]),
+            Err(Error::Conflict("--in-place can't edit standard input"))
+        ));
+    }
+
+    #[test]
+    fn stdin_by_default() {
+        assert_eq!(parse(&["duct"]).unwrap().paths, vec!["-"]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+// the flags in a config file, which are used as defaults for the ones on the command line
+//
+// each line holds a flag along with its value, if it takes one. the value can come after a `=` or
+// a space (`--color=never`, `-C 2`), and everything after the first space is taken as it is, so
+// values with spaces in them don't need quoting. blank lines and lines starting with `#` are
+// skipped
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    // the args on each line that has any, along with its line number
+    pub lines: Vec<(usize, Vec<String>)>,
+}
+
+impl ConfigFile {
+    // reads the config file, if there is one. it not being there is only an error when
+    // MINIGREP_CONFIG asked for it
+    pub fn load() -> Result<Option<ConfigFile>, Error> {
+        let (path, asked_for) = match location() {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(ConfigFile::parse(&path, &contents))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !asked_for => Ok(None),
+            Err(source) => Err(Error::Io { path, source }),
+        }
+    }
+
+    pub fn parse(path: &Path, contents: &str) -> ConfigFile {
+        let lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                let args = match line.split_once(char::is_whitespace) {
+                    Some((flag, value)) => vec![flag.to_string(), value.trim_start().to_string()],
+                    None => vec![line.to_string()],
+                };
+                (number, args)
+            })
+            .collect();
+
+        ConfigFile {
+            path: path.to_path_buf(),
+            lines,
+        }
+    }
+}
+
+// where the config file is, and whether it was asked for with MINIGREP_CONFIG. setting that to
+// nothing turns the config file off. otherwise it's `minigrep/config` in the XDG config directory
+fn location() -> Option<(PathBuf, bool)> {
+    if let Some(path) = env::var_os("MINIGREP_CONFIG") {
+        return (!path.is_empty()).then(|| (PathBuf::from(path), true));
+    }
+
+    // the XDG spec says to ignore XDG_CONFIG_HOME unless it's an absolute path
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some((dir.join("minigrep").join("config"), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let contents = "\
+# ignore case unless there's an uppercase letter
+--smart-case
+
+  -C 2
+--color=never
+-r   some  text
+-in
+";
+        let file = ConfigFile::parse(Path::new("config"), contents);
+
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(
+            file.lines,
+            vec![
+                (2, args(&["--smart-case"])),
+                (4, args(&["-C", "2"])),
+                (5, args(&["--color=never"])),
+                (6, args(&["-r", "some  text"])),
+                (7, args(&["-in"])),
+            ]
+        );
+    }
+}
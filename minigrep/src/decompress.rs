@@ -0,0 +1,114 @@
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+
+use crate::error::WithPath;
+
+// the compression formats `-z` knows how to read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+// works out what `bytes` (the start of a file) was compressed with from the magic bytes every
+// file in that format starts with, if it's compressed at all
+pub fn detect(bytes: &[u8]) -> Option<Format> {
+    match bytes {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        [b'B', b'Z', b'h', b'1'..=b'9', ..] => Some(Format::Bzip2),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Format::Zstd),
+        _ => None,
+    }
+}
+
+// wraps `reader` (the contents of `path`) in whatever it takes to decompress it, or hands it
+// back as it is if it isn't compressed. it's all done as `reader` is read, so only a buffer's
+// worth of the file is ever in memory at once no matter how big it is
+pub fn decoder<'a, R>(mut reader: R, path: &Path) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let format = match detect(reader.fill_buf()?) {
+        Some(format) => format,
+        None => return Ok(Box::new(reader)),
+    };
+
+    // a file that turns out to be corrupt halfway through decompressing it otherwise gives an
+    // error that doesn't say which file it was
+    Ok(match format {
+        // the multi-stream decoders keep going after the first stream, the way gunzip and bunzip2
+        // do with files that were concatenated together
+        Format::Gzip => Box::new(BufReader::new(WithPath::new(
+            path,
+            MultiGzDecoder::new(reader),
+        ))),
+        Format::Bzip2 => Box::new(BufReader::new(WithPath::new(
+            path,
+            MultiBzDecoder::new(reader),
+        ))),
+        Format::Zstd => Box::new(BufReader::new(WithPath::new(
+            path,
+            zstd::Decoder::with_buffer(reader)?,
+        ))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const CONTENTS: &str = "Rust:\nsafe, fast, productive.\nPick three.\n";
+
+    fn decompress(compressed: &[u8]) -> io::Result<String> {
+        let mut decoded = String::new();
+        decoder(compressed, Path::new("poem.txt"))?.read_to_string(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(CONTENTS.as_bytes()).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        assert_eq!(detect(&compressed), Some(Format::Gzip));
+
+        // two files' worth of gzip stuck together reads as both of them
+        compressed.extend_from_within(..);
+        assert_eq!(decompress(&compressed).unwrap(), CONTENTS.repeat(2));
+    }
+
+    #[test]
+    fn bzip2() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(CONTENTS.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(detect(&compressed), Some(Format::Bzip2));
+        assert_eq!(decompress(&compressed).unwrap(), CONTENTS);
+    }
+
+    #[test]
+    fn zstd() {
+        let compressed = zstd::encode_all(CONTENTS.as_bytes(), 1).unwrap();
+        assert_eq!(detect(&compressed), Some(Format::Zstd));
+        assert_eq!(decompress(&compressed).unwrap(), CONTENTS);
+    }
+
+    #[test]
+    fn not_compressed() {
+        assert_eq!(detect(CONTENTS.as_bytes()), None);
+        assert_eq!(detect(b"BZh0"), None);
+        assert_eq!(detect(b""), None);
+        assert_eq!(decompress(CONTENTS.as_bytes()).unwrap(), CONTENTS);
+    }
+
+    #[test]
+    fn corrupt() {
+        let err = decompress(&[0x1f, 0x8b, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]).unwrap_err();
+        assert!(err.to_string().starts_with("poem.txt: "), "{}", err);
+    }
+}
@@ -0,0 +1,65 @@
+// whether `start` (the start of a file) is a UTF-16 byte order mark
+pub fn has_utf16_bom(start: &[u8]) -> bool {
+    matches!(start, [0xff, 0xfe, ..] | [0xfe, 0xff, ..])
+}
+
+// decodes `bytes` into UTF-8 if it starts with a UTF-16 byte order mark, which is left out of
+// what's given back. anything that isn't valid UTF-16 (like half of a surrogate pair, or an odd
+// byte at the end) is replaced with U+FFFD, the same as invalid UTF-8 is everywhere else
+pub fn decode_utf16(bytes: &[u8]) -> Option<String> {
+    let unit: fn([u8; 2]) -> u16 = match bytes {
+        [0xff, 0xfe, ..] => u16::from_le_bytes,
+        [0xfe, 0xff, ..] => u16::from_be_bytes,
+        _ => return None,
+    };
+
+    let units = bytes[2..].chunks(2).map(|pair| match *pair {
+        [a, b] => unit([a, b]),
+        _ => 0xfffd,
+    });
+    let text = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // encodes `text` as UTF-16 with a byte order mark
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for unit in std::iter::once(0xfeff).chain(text.encode_utf16()) {
+            let pair = if little_endian {
+                u16::to_le_bytes(unit)
+            } else {
+                u16::to_be_bytes(unit)
+            };
+            bytes.extend_from_slice(&pair);
+        }
+        bytes
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let text = "duct tape\r\nсиняя изолента 🦆\n";
+        for little_endian in [true, false] {
+            let bytes = utf16(text, little_endian);
+            assert!(has_utf16_bom(&bytes));
+            assert_eq!(decode_utf16(&bytes).as_deref(), Some(text));
+        }
+
+        // half a surrogate pair and a byte left over
+        let mut bytes = utf16("a", true);
+        bytes.extend_from_slice(&[0x3d, 0xd8, b'b', 0, b'c']);
+        assert_eq!(decode_utf16(&bytes).as_deref(), Some("a\u{fffd}b\u{fffd}"));
+    }
+
+    #[test]
+    fn without_bom() {
+        assert!(!has_utf16_bom(b"duct tape"));
+        assert!(!has_utf16_bom(b"\xff"));
+        assert_eq!(decode_utf16(b"d\0u\0c\0t\0"), None);
+    }
+}
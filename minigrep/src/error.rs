@@ -0,0 +1,245 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// everything that can go wrong in minigrep. each variant keeps hold of whatever argument, pattern
+// or path was the problem, so callers can tell what went wrong without picking apart a message
+#[derive(Debug)]
+pub enum Error {
+    // there was no QUERY (and no `-e` or `-f` either)
+    MissingQuery,
+    // a flag that doesn't exist, as it was written (`-x` or `--xyz`)
+    UnknownFlag(String),
+    // a flag that needs a value was the last thing on the command line
+    MissingValue {
+        flag: String,
+        expected: &'static str,
+    },
+    // a flag that doesn't take a value was given one (`--count=2`)
+    UnexpectedValue {
+        flag: String,
+    },
+    // a flag was given a value it can't do anything with
+    InvalidValue {
+        flag: String,
+        value: String,
+        expected: &'static str,
+    },
+    // flags that don't work together, or a flag that needs another one
+    Conflict(&'static str),
+    // a regex that doesn't compile
+    Regex {
+        pattern: String,
+        source: regex::Error,
+    },
+    // a set of patterns from `-e`/`-f` that's too big to compile
+    Patterns {
+        count: usize,
+        source: Box<dyn error::Error + Send + Sync>,
+    },
+    // a query that's too long for `--fuzzy`
+    QueryTooLong {
+        query: String,
+        max: usize,
+    },
+    // a glob for `--include`/`--exclude` that doesn't parse
+    Glob {
+        glob: String,
+        source: globset::Error,
+    },
+    // something in a config file that isn't a flag
+    NotAFlag(String),
+    // a flag that can only be given on the command line, found in a config file
+    NotForConfigFile {
+        flag: String,
+    },
+    // something wrong on a line of the config file
+    ConfigFile {
+        path: PathBuf,
+        line: usize,
+        source: Box<Error>,
+    },
+    // a file or directory that couldn't be read (or written, with `--in-place`)
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // how many files or directories were skipped because they couldn't be read. each one was
+    // reported when it happened, and everything else was still searched
+    Unsearched(usize),
+    // the results couldn't be printed
+    Output(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingQuery => write!(f, "couldn't extract query"),
+            Error::UnknownFlag(flag) => write!(f, "unknown flag `{}`", flag),
+            Error::MissingValue { flag, expected } => write!(f, "{} needs {}", flag, expected),
+            Error::UnexpectedValue { flag } => write!(f, "{} doesn't take a value", flag),
+            Error::InvalidValue {
+                flag,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value `{}` for {}, expected {}",
+                value, flag, expected
+            ),
+            Error::Conflict(message) => write!(f, "{}", message),
+            Error::Regex { pattern, source } => {
+                write!(f, "invalid regex pattern `{}`:\n{}", pattern, source)
+            }
+            Error::Patterns { count, source } => {
+                write!(f, "couldn't compile the {} patterns: {}", count, source)
+            }
+            Error::QueryTooLong { max, .. } => write!(
+                f,
+                "--fuzzy only works with queries of up to {} characters",
+                max
+            ),
+            Error::Glob { glob, source } => {
+                write!(f, "invalid glob `{}`: {}", glob, source.kind())
+            }
+            Error::NotAFlag(arg) => write!(f, "expected a flag, found `{}`", arg),
+            Error::NotForConfigFile { flag } => {
+                write!(f, "{} can't be used in a config file", flag)
+            }
+            Error::ConfigFile { path, line, source } => {
+                write!(f, "{}:{}: {}", path.display(), line, source)
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Unsearched(1) => write!(f, "1 path couldn't be searched"),
+            Error::Unsearched(count) => write!(f, "{} paths couldn't be searched", count),
+            Error::Output(source) => write!(f, "couldn't write output: {}", source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Regex { source, .. } => Some(source),
+            Error::Patterns { source, .. } => Some(source.as_ref()),
+            Error::Glob { source, .. } => Some(source),
+            Error::ConfigFile { source, .. } => Some(source.as_ref()),
+            Error::Io { source, .. } | Error::Output(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// searching passes errors around as `io::Error`s, with the ones about a particular file carrying
+// an `Error::Io` inside them (see `with_path`). anything else went wrong while printing
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        if !has_path(&e) {
+            return Error::Output(e);
+        }
+        match e.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            _ => unreachable!("the error was just checked to be an Error"),
+        }
+    }
+}
+
+// marks `e` as having happened while reading (or writing) `path`, unless it already says which
+// file it's about. the kind of error is kept the same
+pub fn with_path(path: &Path, e: io::Error) -> io::Error {
+    if has_path(&e) {
+        return e;
+    }
+    io::Error::new(
+        e.kind(),
+        Error::Io {
+            path: path.to_path_buf(),
+            source: e,
+        },
+    )
+}
+
+// whether `e` is about a particular file, from having gone through `with_path`
+pub fn has_path(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Error>())
+}
+
+// a reader that says which file it was reading when something goes wrong
+pub struct WithPath<R> {
+    path: PathBuf,
+    inner: R,
+}
+
+impl<R> WithPath<R> {
+    pub fn new(path: &Path, inner: R) -> WithPath<R> {
+        WithPath {
+            path: path.to_path_buf(),
+            inner,
+        }
+    }
+}
+
+impl<R: Read> Read for WithPath<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| with_path(&self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn io_errors_keep_their_path() {
+        let e = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let e = with_path(Path::new("poem.txt"), e);
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        // a second path doesn't replace the first one
+        let e = with_path(Path::new("other.txt"), e);
+
+        match Error::from(e) {
+            Error::Io { path, source } => {
+                assert_eq!(path, Path::new("poem.txt"));
+                assert_eq!(source.to_string(), "gone");
+            }
+            e => panic!("expected an Io error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn other_io_errors_are_output() {
+        let e = Error::from(io::Error::new(io::ErrorKind::WriteZero, "full"));
+        assert!(matches!(e, Error::Output(_)));
+        assert_eq!(e.to_string(), "couldn't write output: full");
+        assert_eq!(e.source().unwrap().to_string(), "full");
+    }
+
+    #[test]
+    fn unsearched() {
+        assert_eq!(
+            Error::Unsearched(1).to_string(),
+            "1 path couldn't be searched"
+        );
+        assert_eq!(
+            Error::Unsearched(3).to_string(),
+            "3 paths couldn't be searched"
+        );
+    }
+
+    #[test]
+    fn reader_errors() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("bad sector"))
+            }
+        }
+
+        let e = WithPath::new(Path::new("poem.txt"), Broken)
+            .read(&mut [0; 4])
+            .unwrap_err();
+        assert_eq!(Error::from(e).to_string(), "poem.txt: bad sector");
+    }
+}
@@ -0,0 +1,158 @@
+use std::ops::Range;
+
+// chars where lowercasing doesn't give the same thing as Unicode's simple case folding. these are
+// mostly alternate lowercase forms (like the final sigma) that lowercasing leaves alone but that
+// should still match the usual form
+const FOLD_EXCEPTIONS: &[(char, char)] = &[
+    ('\u{00B5}', '\u{03BC}'), // micro sign -> mu
+    ('\u{017F}', 's'),        // long s
+    ('\u{0345}', '\u{03B9}'), // combining ypogegrammeni -> iota
+    ('\u{03C2}', '\u{03C3}'), // final sigma -> sigma
+    ('\u{03D0}', '\u{03B2}'), // beta symbol -> beta
+    ('\u{03D1}', '\u{03B8}'), // theta symbol -> theta
+    ('\u{03D5}', '\u{03C6}'), // phi symbol -> phi
+    ('\u{03D6}', '\u{03C0}'), // pi symbol -> pi
+    ('\u{03F0}', '\u{03BA}'), // kappa symbol -> kappa
+    ('\u{03F1}', '\u{03C1}'), // rho symbol -> rho
+    ('\u{03F5}', '\u{03B5}'), // lunate epsilon -> epsilon
+    ('\u{1C80}', '\u{0432}'), // old cyrillic letter forms -> their modern letter
+    ('\u{1C81}', '\u{0434}'),
+    ('\u{1C82}', '\u{043E}'),
+    ('\u{1C83}', '\u{0441}'),
+    ('\u{1C84}', '\u{0442}'),
+    ('\u{1C85}', '\u{0442}'),
+    ('\u{1C86}', '\u{044A}'),
+    ('\u{1C87}', '\u{0463}'),
+    ('\u{1C88}', '\u{A64B}'),
+    ('\u{1E9B}', '\u{1E61}'), // long s with dot above -> s with dot above
+    ('\u{1FBE}', '\u{03B9}'), // greek prosgegrammeni -> iota
+    ('\u{1FD3}', '\u{0390}'), // precomposed greek letters with two ways of being written
+    ('\u{1FE3}', '\u{03B0}'),
+    ('\u{FB05}', '\u{FB06}'), // long s t ligature -> s t ligature
+];
+
+// case folds `c` using Unicode's simple case folding, which always maps one char to one char.
+// that means 'ẞ' folds to 'ß' (not "ss"), and 'İ' folds to itself instead of becoming an 'i' with a
+// combining dot after it like lowercasing does
+pub fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+    if let Ok(i) = FOLD_EXCEPTIONS.binary_search_by_key(&c, |&(from, _)| from) {
+        return FOLD_EXCEPTIONS[i].1;
+    }
+
+    // chars that lowercase to more than one char don't have a simple case folding
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+// a query that's been case folded ahead of time, so lines can be compared against it one char at a
+// time without folding (or allocating) anything per line
+pub struct FoldedQuery {
+    chars: Vec<char>,
+}
+
+impl FoldedQuery {
+    pub fn new(query: &str) -> FoldedQuery {
+        FoldedQuery {
+            chars: query.chars().map(fold).collect(),
+        }
+    }
+
+    // finds the first place at or after `start` in `line` where the query is, ignoring case.
+    // since folding never changes how many chars there are, a match always covers whole chars of
+    // `line` and can be given back as a byte range into it
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let first = match self.chars.first() {
+            Some(&first) => first,
+            None => return Some(start..start),
+        };
+
+        for (i, c) in line[start..].char_indices() {
+            if fold(c) != first {
+                continue;
+            }
+
+            let begin = start + i;
+            if let Some(end) = self.matches_at(line, begin) {
+                return Some(begin..end);
+            }
+        }
+
+        None
+    }
+
+    // checks whether the query is at byte `begin` of `line`, giving back where it ends if so
+    fn matches_at(&self, line: &str, begin: usize) -> Option<usize> {
+        let mut rest = line[begin..].char_indices();
+        for &q in &self.chars {
+            match rest.next() {
+                Some((_, c)) if fold(c) == q => {}
+                _ => return None,
+            }
+        }
+
+        Some(rest.next().map_or(line.len(), |(i, _)| begin + i))
+    }
+}
+
+// whether `query` has anything uppercase in it, for smart case. in a regex anything right after a
+// `\` is skipped, so escapes like `\S` and `\W` don't count
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exceptions_are_sorted() {
+        assert!(FOLD_EXCEPTIONS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn simple_folding() {
+        assert_eq!(fold('A'), 'a');
+        assert_eq!(fold('Σ'), 'σ');
+        assert_eq!(fold('ς'), 'σ');
+        assert_eq!(fold('ẞ'), 'ß');
+        assert_eq!(fold('K'), 'k'); // kelvin sign
+        assert_eq!(fold('İ'), 'İ');
+    }
+
+    #[test]
+    fn find() {
+        let query = FoldedQuery::new("straße");
+        assert_eq!(query.find_at("GROẞE STRAẞE", 0), Some(8..16));
+        assert_eq!(query.find_at("STRASSE", 0), None);
+
+        let query = FoldedQuery::new("ΣΟΦΟΣ");
+        assert_eq!(query.find_at("ο σοφος", 0), Some(3..13));
+
+        let query = FoldedQuery::new("istanbul");
+        assert_eq!(query.find_at("İstanbul", 0), None);
+        assert_eq!(query.find_at("ISTANBUL istanbul", 1), Some(9..17));
+    }
+
+    #[test]
+    fn uppercase() {
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase("rust", false));
+        assert!(!has_uppercase(r"\S+\w", true));
+        assert!(has_uppercase(r"\S+R", true));
+        assert!(has_uppercase(r"\S", false));
+    }
+}
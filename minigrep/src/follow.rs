@@ -0,0 +1,425 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::with_path;
+use crate::output::{self, Printer, Stats};
+use crate::{
+    is_binary, matcher, skip_binary, skip_failed, Binary, Config, Matcher, Mode, Searcher,
+};
+
+// how long to wait before checking the files for anything new again
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// how much of a file to read at once
+const READ_CHUNK_LEN: usize = 64 * 1024;
+
+// searches `files` and then keeps watching them for new lines (`--follow`), like `tail -f | grep`.
+// this only ever stops on its own when `-m` has been reached in every file, so it's usually up to
+// whoever's running it to stop it
+//
+// each file gets a printer of its own, so things like `-m` and context are kept track of for each
+// file separately even when new lines show up in several of them at once
+//
+// a file that can't be read is reported, counted in `failed` and no longer followed, while the
+// others carry on
+pub fn follow(
+    config: &Config,
+    files: &[PathBuf],
+    with_path: bool,
+    failed: &mut usize,
+) -> io::Result<bool> {
+    let matcher = matcher(config);
+    let mut followed = Vec::with_capacity(files.len());
+    for path in files {
+        let file = match skip_failed(Followed::open(path, config.binary), failed)? {
+            Some(file) => file,
+            None => continue,
+        };
+        let mut printer = Printer::new(config, with_path, io::stdout());
+        printer.begin(path);
+        followed.push((file, printer));
+    }
+
+    loop {
+        let mut following = false;
+        for (file, printer) in &mut followed {
+            if !file.done {
+                if skip_failed(file.poll(&matcher, printer), failed)?.is_none() {
+                    file.done = true;
+                }
+                following |= !file.done;
+            }
+        }
+        io::stdout().flush()?;
+
+        if !following {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let mut stats = Stats::default();
+    for (_, printer) in &mut followed {
+        printer.end()?;
+        stats.add(&printer.stats());
+    }
+    if config.mode == Mode::Json {
+        output::print_summary(&mut io::stdout(), &stats)?;
+    }
+    Ok(stats.found(config.mode))
+}
+
+// a file that's being followed
+struct Followed {
+    path: PathBuf,
+    file: File,
+    // which file `file` is, to tell when `path` has been pointed at a different one
+    id: Option<FileId>,
+    // how much of `file` has been read
+    pos: u64,
+    // what's been read past the last newline, which is held on to until the rest of its line
+    // shows up
+    partial: Vec<u8>,
+    // how many lines and bytes of the file have been searched so far
+    line_number: usize,
+    byte_offset: usize,
+    // whether the start of the file has been read yet, to check if it's binary, and what to do if
+    // it is
+    checked: bool,
+    binary: Binary,
+    // set when there's no point reading any more of the file, because it's binary or `-m` has
+    // been reached
+    done: bool,
+}
+
+impl Followed {
+    fn open(path: &Path, binary: Binary) -> io::Result<Followed> {
+        let file = File::open(path).map_err(|e| with_path(path, e))?;
+        let id = file.metadata().ok().and_then(|metadata| file_id(&metadata));
+
+        Ok(Followed {
+            path: path.to_path_buf(),
+            file,
+            id,
+            pos: 0,
+            partial: Vec::new(),
+            line_number: 0,
+            byte_offset: 0,
+            checked: false,
+            binary,
+            done: false,
+        })
+    }
+
+    // searches whatever's been added to the file since it was last looked at, and then checks
+    // whether it's been rotated (a new file moved into its place, like logrotate does) or
+    // truncated. either way the new contents are searched from the start, as a search of their own
+    // with its own context and `-m`
+    //
+    // rotation can only be noticed on unix, where files have an inode number to tell them apart
+    fn poll<M, W>(&mut self, matcher: M, printer: &mut Printer<W>) -> io::Result<()>
+    where
+        M: Matcher,
+        W: Write,
+    {
+        self.read(&matcher, printer)?;
+        if self.done {
+            return Ok(());
+        }
+
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // the file has been moved away and nothing has taken its place yet. anything that's
+            // still written to the old one keeps being read until something does
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(with_path(&self.path, e)),
+        };
+
+        if file_id(&metadata) != self.id {
+            eprintln!(
+                "minigrep: {}: file replaced, following the new one",
+                self.path.display()
+            );
+            self.finish(&matcher, printer)?;
+            let path = self.path.clone();
+            *self = Followed::open(&path, self.binary)?;
+        } else if metadata.len() < self.pos {
+            eprintln!("minigrep: {}: file truncated", self.path.display());
+            self.finish(&matcher, printer)?;
+            self.file
+                .seek(SeekFrom::Start(0))
+                .map_err(|e| with_path(&self.path, e))?;
+            self.pos = 0;
+            self.line_number = 0;
+            self.byte_offset = 0;
+            self.checked = false;
+            self.done = false;
+        } else {
+            return Ok(());
+        }
+
+        printer.end()?;
+        printer.begin(&self.path);
+        self.read(&matcher, printer)
+    }
+
+    // searches every whole line that's been added to the file since the last read. it's read a
+    // chunk at a time, so only the line being read has to be held on to however much was added
+    fn read<M, W>(&mut self, matcher: M, printer: &mut Printer<W>) -> io::Result<()>
+    where
+        M: Matcher,
+        W: Write,
+    {
+        let mut chunk = vec![0; READ_CHUNK_LEN];
+        while !self.done {
+            let read = match self.file.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(with_path(&self.path, e)),
+            };
+            self.pos += read as u64;
+            self.partial.extend_from_slice(&chunk[..read]);
+
+            if !self.checked {
+                self.checked = true;
+                // the same check searching a file normally does, which only looks at the start of
+                // it
+                if self.binary != Binary::Text && is_binary(&self.partial) {
+                    if self.binary == Binary::Skip {
+                        skip_binary(&self.path);
+                        // the check is redone for whatever's searched after a `begin`, so this only
+                        // takes back the one for these contents
+                        printer.skip();
+                        self.done = true;
+                        return Ok(());
+                    }
+                    printer.binary();
+                }
+            }
+
+            let end = match memchr::memrchr(b'\n', &self.partial) {
+                Some(i) => i + 1,
+                None => continue,
+            };
+            let mut partial = std::mem::take(&mut self.partial);
+            let searched = self.search(&partial[..end], &matcher, printer);
+            partial.drain(..end);
+            self.partial = partial;
+            searched?;
+        }
+
+        Ok(())
+    }
+
+    // searches what's left of the file after its last newline, since nothing more is going to be
+    // added to it
+    fn finish<M, W>(&mut self, matcher: M, printer: &mut Printer<W>) -> io::Result<()>
+    where
+        M: Matcher,
+        W: Write,
+    {
+        let rest = std::mem::take(&mut self.partial);
+        self.search(&rest, matcher, printer)
+    }
+
+    // hands the lines in `bytes` to `printer`, numbered on from the lines that came before them
+    fn search<M, W>(&mut self, bytes: &[u8], matcher: M, printer: &mut Printer<W>) -> io::Result<()>
+    where
+        M: Matcher,
+        W: Write,
+    {
+        let (line_number, byte_offset) = (self.line_number, self.byte_offset);
+        let mut lines = 0;
+        let mut keep_going = true;
+
+        Searcher::new(matcher).search_slice_lines(bytes, |mut line| {
+            line.line_number += line_number;
+            line.byte_offset += byte_offset;
+            lines += 1;
+            keep_going = printer.line(&line)?;
+            Ok(keep_going)
+        })?;
+
+        self.line_number += lines;
+        self.byte_offset += bytes.len();
+        // with `-m`, stop as soon as the last match is in instead of waiting for the next line
+        self.done = !keep_going || printer.finished();
+        Ok(())
+    }
+}
+
+// what tells one file apart from another, even once it's been renamed
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<FileId> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Literal;
+    use std::fs::OpenOptions;
+
+    fn config(args: &[&str]) -> Config {
+        Config::for_test(args).unwrap()
+    }
+
+    // polls `file` once, giving back what was printed
+    fn poll(config: &Config, file: &mut Followed) -> String {
+        let mut out = Vec::new();
+        let mut printer = Printer::new(config, false, &mut out);
+        printer.begin(&file.path);
+        file.poll(Literal::new(&config.patterns[0]), &mut printer)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn appended_lines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("app.log");
+        fs::write(&path, "error one\nfine\n").unwrap();
+        let config = config(&["-n", "--follow", "error", path.to_str().unwrap()]);
+
+        let mut file = Followed::open(&path, config.binary).unwrap();
+        let first = poll(&config, &mut file);
+        let nothing_new = poll(&config, &mut file);
+        // half a line isn't searched until the rest of it shows up
+        append(&path, "fine\nerror t");
+        let half = poll(&config, &mut file);
+        append(&path, "wo\n");
+        let second = poll(&config, &mut file);
+
+        // truncated and written over with something shorter
+        fs::write(&path, "error\n").unwrap();
+        let truncated = poll(&config, &mut file);
+
+        // rotated: moved away, with a new file taking its place
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&dir.join("app.log.1"), "error in old file\n");
+        let moved = poll(&config, &mut file);
+        fs::write(&path, "new\nerror three\n").unwrap();
+        let rotated = poll(&config, &mut file);
+
+        assert_eq!(first, "1:error one\n");
+        assert_eq!(nothing_new, "");
+        assert_eq!(half, "");
+        assert_eq!(second, "4:error two\n");
+        assert_eq!(truncated, "1:error\n");
+        assert_eq!(moved, "2:error in old file\n");
+        if cfg!(unix) {
+            assert_eq!(rotated, "2:error three\n");
+        }
+    }
+
+    #[test]
+    fn lines_across_chunks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("app.log");
+        // long enough for some of the lines to be split between two reads
+        let contents = "fine\nerror here\n".repeat(READ_CHUNK_LEN / 4);
+        fs::write(&path, &contents).unwrap();
+        let config = config(&["--follow", "error", path.to_str().unwrap()]);
+
+        let mut file = Followed::open(&path, config.binary).unwrap();
+        let mut printer = Printer::new(&config, false, Vec::new());
+        printer.begin(&path);
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+
+        assert_eq!(printer.stats().matched_lines, READ_CHUNK_LEN / 4);
+        assert_eq!(file.line_number, READ_CHUNK_LEN / 2);
+        assert!(file.partial.is_empty());
+    }
+
+    #[test]
+    fn rotated_starts_over() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("app.log");
+        fs::write(&path, "fine\nerror one\n").unwrap();
+        let config = config(&["-A1", "-m1", "--follow", "error", path.to_str().unwrap()]);
+
+        let mut file = Followed::open(&path, config.binary).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, false, &mut out);
+        printer.begin(&path);
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+        // truncated, and then rotated to a binary file that's skipped
+        fs::write(&path, "error 2\nok\n").unwrap();
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "error\0\n").unwrap();
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+        printer.end().unwrap();
+        let stats = printer.stats();
+
+        // the context after the first match isn't taken from the new contents, and `-m` starts over
+        assert_eq!(out, b"error one\n--\nerror 2\nok\n");
+        if cfg!(unix) {
+            assert_eq!(stats.searches, 2);
+            assert_eq!(stats.searches_with_match, 2);
+        }
+    }
+
+    #[test]
+    fn binary_skipped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("app.bin");
+        fs::write(&path, "error\0\nerror\n").unwrap();
+        let config = config(&["--follow", "error", path.to_str().unwrap()]);
+
+        let mut file = Followed::open(&path, config.binary).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, false, &mut out);
+        printer.begin(&path);
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+
+        assert!(file.done);
+        assert_eq!(printer.stats(), Stats::default());
+        assert_eq!(out, b"");
+    }
+
+    #[test]
+    fn max_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("app.log");
+        fs::write(&path, "error one\n").unwrap();
+        let config = config(&["-m2", "--follow", "error", path.to_str().unwrap()]);
+
+        let mut file = Followed::open(&path, config.binary).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, false, &mut out);
+        printer.begin(&path);
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+        assert!(!file.done);
+        append(&path, "fine\nerror two\nerror three\n");
+        file.poll(Literal::new("error"), &mut printer).unwrap();
+
+        assert!(file.done);
+        assert_eq!(out, b"error one\nerror two\n");
+    }
+}
@@ -0,0 +1,211 @@
+use std::ops::Range;
+
+use crate::error::Error;
+use crate::fold::fold;
+
+// the longest query `--fuzzy` can look for, since each char of it takes up a bit of a u64
+pub const MAX_QUERY_LEN: usize = 64;
+
+// a query that's looked for approximately, so that anything within `max_errors` edits (chars
+// inserted, deleted or swapped for another) of it counts as a match
+//
+// lines are scanned with the bit-parallel Bitap algorithm (with Wu and Manber's extension for
+// errors), which keeps track of every prefix of the query that could end at the current char for
+// every number of errors at once. that only finds where a match ends, so the start of it is worked
+// out afterwards with a small edit distance table over just the chars right before the end
+pub struct FuzzyQuery {
+    chars: Vec<char>,
+    max_errors: usize,
+    case_sensitive: bool,
+    // which positions in the query each char is at, as bit masks. ASCII gets a table of its own
+    // since it's most of what's ever looked up
+    ascii_masks: [u64; 128],
+    masks: Vec<(char, u64)>,
+}
+
+impl FuzzyQuery {
+    pub fn new(query: &str, max_errors: usize, case_sensitive: bool) -> Result<FuzzyQuery, Error> {
+        let fold_case = |c| if case_sensitive { c } else { fold(c) };
+        let chars: Vec<char> = query.chars().map(fold_case).collect();
+        if chars.len() > MAX_QUERY_LEN {
+            return Err(Error::QueryTooLong {
+                query: query.to_string(),
+                max: MAX_QUERY_LEN,
+            });
+        }
+
+        let mut ascii_masks = [0; 128];
+        let mut masks: Vec<(char, u64)> = Vec::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if c.is_ascii() {
+                ascii_masks[c as usize] |= 1 << i;
+            } else {
+                match masks.iter_mut().find(|(other, _)| *other == c) {
+                    Some((_, mask)) => *mask |= 1 << i,
+                    None => masks.push((c, 1 << i)),
+                }
+            }
+        }
+        masks.sort_unstable();
+
+        Ok(FuzzyQuery {
+            chars,
+            max_errors,
+            case_sensitive,
+            ascii_masks,
+            masks,
+        })
+    }
+
+    fn mask(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            return self.ascii_masks[c as usize];
+        }
+        self.masks
+            .binary_search_by_key(&c, |&(c, _)| c)
+            .map_or(0, |i| self.masks[i].1)
+    }
+
+    fn fold_case(&self, c: char) -> char {
+        if self.case_sensitive {
+            c
+        } else {
+            fold(c)
+        }
+    }
+
+    // finds the first approximate match at or after `start` in `line`. the end of the match is
+    // the first place a close enough match ends, pushed along for as long as that doesn't add any
+    // errors (so "hello" is matched in full, instead of just "hell" with one char missing)
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let len = self.chars.len();
+        // with this many errors allowed, every query could be deleted down to nothing
+        if self.max_errors >= len {
+            return Some(start..start);
+        }
+
+        let last = 1 << (len - 1);
+        // `rows[d]` has bit i set when the first i + 1 chars of the query match, with at most d
+        // errors, right up to the char that was just read. at the start the only way to match
+        // the first i chars is to have deleted them all
+        let mut rows: Vec<u64> = (0..=self.max_errors).map(|d| (1 << d) - 1).collect();
+        let mut best: Option<(usize, usize)> = None;
+
+        for (i, c) in line[start..].char_indices() {
+            let mask = self.mask(self.fold_case(c));
+            let mut prev_old = rows[0];
+            rows[0] = ((rows[0] << 1) | 1) & mask;
+            for d in 1..rows.len() {
+                let old = rows[d];
+                rows[d] = (((old << 1) | 1) & mask) // the char matches
+                    | prev_old // the char is an extra one
+                    | (prev_old << 1) | 1 // the char replaces one from the query
+                    | (rows[d - 1] << 1); // a char from the query is missing here
+                prev_old = old;
+            }
+
+            let end = start + i + c.len_utf8();
+            let errors = rows.iter().position(|row| row & last != 0);
+            match (errors, best) {
+                (Some(errors), Some((best_errors, _))) if errors <= best_errors => {
+                    best = Some((errors, end));
+                }
+                (_, Some(_)) => break,
+                (Some(errors), None) => best = Some((errors, end)),
+                (None, None) => {}
+            }
+        }
+
+        let (errors, end) = best?;
+        Some(self.start_of(line, start, end, errors)..end)
+    }
+
+    // works out where a match with `errors` errors that ends at `end` starts, by filling in the
+    // edit distance between the query and each stretch of `line` that ends at `end`. it's done
+    // backwards from `end`, comparing the query from its last char, so each column of the table
+    // covers one more char of `line`. the shortest stretch that's as close as the match is wins
+    fn start_of(&self, line: &str, start: usize, end: usize, errors: usize) -> usize {
+        let len = self.chars.len();
+        // `column[i]` is the edit distance between the last i chars of the query and the chars
+        // of `line` looked at so far
+        let mut column: Vec<usize> = (0..=len).collect();
+        let mut next = vec![0; len + 1];
+
+        for (j, (i, c)) in line[start..end].char_indices().rev().enumerate() {
+            let c = self.fold_case(c);
+            next[0] = j + 1;
+            for q in 1..=len {
+                let swap = column[q - 1] + usize::from(self.chars[len - q] != c);
+                next[q] = swap.min(column[q] + 1).min(next[q - 1] + 1);
+            }
+            std::mem::swap(&mut column, &mut next);
+
+            if column[len] <= errors {
+                return start + i;
+            }
+            // the match can't start any further back than the query is long, plus the errors
+            if j + 1 >= len + errors {
+                break;
+            }
+        }
+
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, max_errors: usize, line: &str) -> Option<Range<usize>> {
+        FuzzyQuery::new(query, max_errors, true)
+            .unwrap()
+            .find_at(line, 0)
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(find("duct", 0, "duct tape"), Some(0..4));
+        assert_eq!(find("duct", 0, "dust tape"), None);
+        assert_eq!(find("tape", 1, "duct tape"), Some(5..9));
+    }
+
+    #[test]
+    fn errors() {
+        // swapped, extra and missing chars
+        assert_eq!(find("connection", 1, "lost conection to db"), Some(5..14));
+        assert_eq!(find("connection", 1, "lost connnection to db"), Some(5..16));
+        assert_eq!(find("connection", 1, "lost connectoin to db"), None);
+        assert_eq!(find("connection", 2, "lost connectoin to db"), Some(5..15));
+        assert_eq!(find("timeout", 1, "request timeuot"), None);
+        assert_eq!(find("timeout", 2, "request timeuot"), Some(8..15));
+    }
+
+    #[test]
+    fn whole_query_when_possible() {
+        assert_eq!(find("hello", 1, "say hello"), Some(4..9));
+        assert_eq!(find("hello", 1, "say hell"), Some(4..8));
+    }
+
+    #[test]
+    fn unicode_and_case() {
+        assert_eq!(find("straße", 1, "die strasse"), None);
+        assert_eq!(find("straße", 2, "die strasse"), Some(4..11));
+        let query = FuzzyQuery::new("ΣΟΦΟΣ", 1, false).unwrap();
+        assert_eq!(query.find_at("ο σοφς", 0), Some(3..11));
+        assert_eq!(query.find_at("ο σοφς", 3), Some(3..11));
+        assert_eq!(query.find_at("ο σοφς", 5), None);
+    }
+
+    #[test]
+    fn everything_matches_with_enough_errors() {
+        assert_eq!(find("ab", 2, "xyz"), Some(0..0));
+    }
+
+    #[test]
+    fn too_long() {
+        let query = "x".repeat(MAX_QUERY_LEN + 1);
+        assert!(FuzzyQuery::new(&query, 1, true).is_err());
+        assert!(FuzzyQuery::new(&query[1..], 1, true).is_ok());
+    }
+}
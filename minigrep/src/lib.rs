@@ -1,103 +1,594 @@
-use std::env;
-use std::error::Error;
-use std::fs;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 
-use rand::{thread_rng, Rng};
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+use server::ThreadPool;
 
-pub struct Config {
-    pub query: String,
-    pub filename: String,
-    pub case_sensitive: bool,
+mod config;
+mod config_file;
+mod decompress;
+mod encoding;
+mod error;
+mod fold;
+mod follow;
+mod fuzzy;
+mod matcher;
+mod mmap;
+mod output;
+mod replace;
+mod searcher;
+mod substring;
+mod walk;
+
+pub use config::{Action, Binary, Color, Config, Mode, USAGE, VERSION};
+pub use error::Error;
+use error::WithPath;
+pub use fuzzy::FuzzyQuery;
+pub use matcher::{Boundary, Bounded, CaseInsensitive, Literal, Matcher};
+use output::{Printer, Stats};
+pub use searcher::{Line, Searcher};
+pub use walk::Filter;
+
+// compiles `pattern`, turning the regex crate's error into one that says which pattern was bad
+pub fn compile_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|source| Error::Regex {
+            pattern: pattern.to_string(),
+            source,
+        })
 }
 
-impl Config {
-    // checks that there are sufficient arguments to make a Config
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
-        // skip first arg (just name of program)
-        args.next();
+// sets the flags a regex needs to run over a whole file at once (`-U`): `.` matches line breaks
+// too, and `^` and `$` match at the start and end of every line, whether it ends in "\n" or "\r\n"
+fn multiline_regex(pattern: &str) -> String {
+    format!("(?msR){}", pattern)
+}
 
-        // iterate to get query and filename
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("couldn't extract query"),
-        };
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("couldn't extract filename"),
-        };
+// compiles a regex that matches any of `patterns` as plain text, within `boundary`. longer patterns
+// go first, so when several match at the same place the longest one wins like it does with
+// `compile_keywords`
+pub fn compile_literals<P: AsRef<str>>(
+    patterns: &[P],
+    case_sensitive: bool,
+    boundary: Boundary,
+    multiline: bool,
+) -> Result<Regex, Error> {
+    let count = patterns.len();
+    let mut patterns: Vec<&str> = patterns.iter().map(AsRef::as_ref).collect();
+    patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.len()));
+    let escaped: Vec<String> = patterns.into_iter().map(regex::escape).collect();
+    let mut pattern = boundary.wrap(&escaped.join("|"));
+    if multiline {
+        pattern = multiline_regex(&pattern);
+    }
 
-        Ok(Config {
-            case_sensitive: env::var("CASE_INSENSITIVE").is_err(),
-            query,
-            filename,
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| Error::Patterns {
+            count,
+            source: Box::new(e),
         })
+}
+
+// builds an Aho-Corasick automaton that finds all of `patterns` in a single pass, however many of
+// them there are
+pub fn compile_keywords<P: AsRef<str>>(patterns: &[P]) -> Result<AhoCorasick, Error> {
+    AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(patterns.iter().map(AsRef::as_ref))
+        .map_err(|e| Error::Patterns {
+            count: patterns.len(),
+            source: Box::new(e),
+        })
+}
+
+// runs the search `config` describes, giving back whether it found anything (or with `-L`, any
+// file without a match)
+pub fn run(mut config: Config) -> Result<bool, Error> {
+    match config.action {
+        Action::Search => {}
+        Action::Help => {
+            println!("{}", USAGE);
+            return Ok(true);
+        }
+        Action::Version => {
+            println!("{}", VERSION);
+            return Ok(true);
+        }
     }
 
-    // checks that there are sufficient arguments to make a Config
-    pub fn new_random(mut args: env::Args) -> Result<Config, &'static str> {
-        // skip first arg (just name of program)
-        args.next();
+    // how many files (or directories) couldn't be searched. the rest are still searched, but the
+    // search as a whole has failed
+    let (files, mut failed) = walk::files(&config.paths, &config.filter);
 
-        // iterate to get query and filename
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("couldn't extract query"),
-        };
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("couldn't extract filename"),
+    if config.color == Color::Auto {
+        config.color = if output::color_stdout() {
+            Color::Always
+        } else {
+            Color::Never
         };
-
-        let mut rng = thread_rng();
-        Ok(Config {
-            case_sensitive: rng.gen_bool(0.5),
-            query,
-            filename,
-        })
     }
-}
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    // read file
-    let contents = fs::read_to_string(config.filename)?;
+    // like grep, only say which file a line came from when there's more than one it could be from.
+    // that's decided by what was asked for, so a path that couldn't be read still counts
+    let with_path = config.paths.len() > 1 || config.paths.iter().any(|p| Path::new(p).is_dir());
 
-    // get results of search
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = if config.in_place {
+        edit_files(&config, &files, &mut out, &mut failed)
+    } else if config.follow {
+        follow::follow(&config, &files, with_path, &mut failed)
+    } else if config.threads > 1 && files.len() > 1 {
+        search_files_parallel(Arc::new(config), files, with_path, &mut out, &mut failed)
     } else {
-        search_case_insensitive(&config.query, &contents)
+        let mut printer = Printer::new(&config, with_path, &mut out);
+        search_files(&config, &files, &mut printer, &mut failed)
+            .and_then(|()| printer.summary())
+            .map(|()| printer.stats().found(config.mode))
     };
 
-    // print results out
-    for result in results {
-        println!("{}", result);
+    match result {
+        // whoever was reading our output (like `head`) has stopped, so there's no point going on.
+        // they got at least one line of it, so something was found
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+        Ok(_) if failed > 0 => Err(Error::Unsearched(failed)),
+        result => Ok(result?),
+    }
+}
+
+// like grep, one file that can't be read shouldn't stop the others from being searched. so an
+// error about a particular file is reported and counted in `failed`, giving back None instead.
+// anything else (like not being able to print) is still given back as an error
+fn skip_failed<T>(result: io::Result<T>, failed: &mut usize) -> io::Result<Option<T>> {
+    match result {
+        Err(e) if error::has_path(&e) => {
+            eprintln!("minigrep: {}", Error::from(e));
+            *failed += 1;
+            Ok(None)
+        }
+        result => result.map(Some),
+    }
+}
+
+fn search_files<W: Write>(
+    config: &Config,
+    files: &[PathBuf],
+    printer: &mut Printer<W>,
+    failed: &mut usize,
+) -> io::Result<()> {
+    let find = matcher(config);
+
+    for file in files {
+        skip_failed(search_file(config, file, &find, printer), failed)?;
     }
 
     Ok(())
 }
 
+// makes the replacements in each of `files` (for `--in-place`), giving back whether any of them
+// had a match
+fn edit_files<W: Write>(
+    config: &Config,
+    files: &[PathBuf],
+    out: &mut W,
+    failed: &mut usize,
+) -> io::Result<bool> {
+    let find = matcher(config);
+    let mut matched = false;
+
+    for file in files {
+        let result = replace::edit_file(config, file, &find, out);
+        matched |= skip_failed(result, failed)?.unwrap_or(false);
+    }
+
+    Ok(matched)
+}
+
+// searches `files` using a pool of `config.threads` threads. each file's output is collected on
+// its own and then printed in the same order as `files`, so the output doesn't change based on
+// which thread happens to finish first
+fn search_files_parallel<W: Write>(
+    config: Arc<Config>,
+    files: Vec<PathBuf>,
+    with_path: bool,
+    out: &mut W,
+    failed: &mut usize,
+) -> io::Result<bool> {
+    let pool = ThreadPool::quiet(config.threads);
+    let (sender, receiver) = mpsc::channel();
+    // set when we've stopped printing early, so the searches still waiting in the pool can be
+    // skipped instead of holding up the pool being dropped
+    let stop = Arc::new(AtomicBool::new(false));
+
+    for (i, file) in files.into_iter().enumerate() {
+        let config = Arc::clone(&config);
+        let sender = sender.clone();
+        let stop = Arc::clone(&stop);
+
+        pool.execute(move || {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let mut buf = Vec::new();
+            let mut printer = Printer::new(&config, with_path, &mut buf);
+            let result = search_file(&config, &file, matcher(&config), &mut printer);
+            let stats = printer.stats();
+            // the receiver is only gone if we stopped early, in which case nobody wants this
+            let _ = sender.send((i, result.map(|()| (buf, stats))));
+        });
+    }
+    drop(sender);
+
+    let result = print_in_order(&config, receiver, out, failed);
+    if result.is_err() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    result.map(|stats| stats.found(config.mode))
+}
+
+// the output of searching one file on its own, tagged with where the file is in the list of files
+type FileOutput = (usize, io::Result<(Vec<u8>, Stats)>);
+
+// prints the output sent over `receiver` in order of the index it's tagged with. output that shows
+// up before its turn is held on to until everything before it has been printed. gives back the
+// stats of every file put together. files that couldn't be read are reported in their turn too
+fn print_in_order<W: Write>(
+    config: &Config,
+    receiver: mpsc::Receiver<FileOutput>,
+    out: &mut W,
+    failed: &mut usize,
+) -> io::Result<Stats> {
+    let separators = output::uses_separators(config);
+    let mut waiting = HashMap::new();
+    let mut next = 0;
+    let mut printed_any = false;
+    let mut stats = Stats::default();
+
+    for (i, result) in receiver {
+        waiting.insert(i, result);
+
+        while let Some(result) = waiting.remove(&next) {
+            next += 1;
+            let (buf, file_stats) = match skip_failed(result, failed)? {
+                Some(output) => output,
+                None => continue,
+            };
+            // each file was printed on its own, so the separator between groups of context that
+            // are in different files has to be added here
+            if separators && printed_any && !buf.is_empty() {
+                writeln!(out, "--")?;
+            }
+            printed_any |= !buf.is_empty();
+            out.write_all(&buf)?;
+            stats.add(&file_stats);
+        }
+    }
+
+    if config.mode == Mode::Json {
+        output::print_summary(out, &stats)?;
+    }
+    Ok(stats)
+}
+
+// hands every line of `file` (or stdin) to `printer`, decompressing it first with `-z`. big files
+// are memory mapped and searched in place, unless that's been turned off
+fn search_file<M, W>(
+    config: &Config,
+    file: &Path,
+    matcher: M,
+    printer: &mut Printer<W>,
+) -> io::Result<()>
+where
+    M: Matcher,
+    W: Write,
+{
+    let searcher = Searcher::new(matcher).multiline(config.multiline);
+
+    if file == Path::new(walk::STDIN) {
+        let stdin = io::stdin();
+        let path = Path::new("(standard input)");
+        let reader = BufReader::new(WithPath::new(path, stdin.lock()));
+        if config.decompress {
+            let reader = decompress::decoder(reader, path)?;
+            return search_input(reader, path, &searcher, config.binary, printer);
+        }
+        return search_input(reader, path, &searcher, config.binary, printer);
+    }
+
+    let f = File::open(file).map_err(|e| error::with_path(file, e))?;
+    // with `--no-mmap`, mapping the file isn't even tried
+    let map = if config.mmap { mmap::map(&f) } else { None };
+    if let Some(map) = map {
+        if config.decompress && decompress::detect(&map).is_some() {
+            let reader = decompress::decoder(&map[..], file)?;
+            return search_input(reader, file, &searcher, config.binary, printer);
+        }
+        return search_slice(&map, file, &searcher, config.binary, printer);
+    }
+    let reader = BufReader::new(WithPath::new(file, f));
+    if config.decompress {
+        let reader = decompress::decoder(reader, file)?;
+        return search_input(reader, file, &searcher, config.binary, printer);
+    }
+    search_input(reader, file, &searcher, config.binary, printer)
+}
+
+// hands every line of `reader` (which has the contents of `path`) to `printer`, unless it looks
+// like binary and `binary` says to skip it. UTF-16 is decoded first, which means reading all of it
+// into memory
+fn search_input<R, M, W>(
+    mut reader: R,
+    path: &Path,
+    searcher: &Searcher<M>,
+    binary: Binary,
+    printer: &mut Printer<W>,
+) -> io::Result<()>
+where
+    R: BufRead,
+    M: Matcher,
+    W: Write,
+{
+    let start = reader.fill_buf()?;
+    if encoding::has_utf16_bom(start) {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        return search_slice(&bytes, path, searcher, binary, printer);
+    }
+    if !begin(start, path, binary, printer) {
+        return Ok(());
+    }
+
+    searcher.search_lines(reader, |line| printer.line(&line))?;
+    printer.end()
+}
+
+// the same as `search_input`, for the contents of a file that are all in memory already
+fn search_slice<M, W>(
+    bytes: &[u8],
+    path: &Path,
+    searcher: &Searcher<M>,
+    binary: Binary,
+    printer: &mut Printer<W>,
+) -> io::Result<()>
+where
+    M: Matcher,
+    W: Write,
+{
+    if let Some(text) = encoding::decode_utf16(bytes) {
+        return search_slice(text.as_bytes(), path, searcher, binary, printer);
+    }
+    if !begin(bytes, path, binary, printer) {
+        return Ok(());
+    }
+
+    if printer.wants_every_line() {
+        searcher.search_slice_lines(bytes, |line| printer.line(&line))?;
+    } else {
+        searcher.search_slice(bytes, |line| printer.line(&line))?;
+    }
+    printer.end()
+}
+
+// gets `printer` ready for the lines of `path`, which starts with `start`, unless it's binary and
+// `binary` says to skip it. gives back whether to search it
+fn begin<W: Write>(start: &[u8], path: &Path, binary: Binary, printer: &mut Printer<W>) -> bool {
+    if binary != Binary::Text && is_binary(start) {
+        if binary == Binary::Skip {
+            skip_binary(path);
+            return false;
+        }
+        printer.begin(path);
+        printer.binary();
+        return true;
+    }
+
+    printer.begin(path);
+    true
+}
+
+// lets whoever's running the search know that `path` wasn't searched because it's binary
+fn skip_binary(path: &Path) {
+    eprintln!(
+        "minigrep: {}: binary file, skipping it (--binary or -a would search it)",
+        path.display()
+    );
+}
+
+// builds the matcher that finds whatever `config` is searching for in a line
+fn matcher(config: &Config) -> Box<dyn Matcher + '_> {
+    let matcher = plain_matcher(config);
+    // a regex already only matches within the boundary
+    if config.boundary == Boundary::Any || config.regex.is_some() {
+        return matcher;
+    }
+    Box::new(Bounded::new(matcher, config.boundary))
+}
+
+// builds the matcher for whatever `config` is searching for, without caring about `-w` or `-x`
+fn plain_matcher(config: &Config) -> Box<dyn Matcher + '_> {
+    if let Some(fuzzy) = &config.fuzzy {
+        Box::new(fuzzy)
+    } else if let Some(re) = &config.regex {
+        Box::new(re)
+    } else if let Some(keywords) = &config.keywords {
+        Box::new(keywords)
+    } else if config.case_sensitive {
+        Box::new(Literal::new(&config.patterns[0]))
+    } else {
+        Box::new(CaseInsensitive::new(&config.patterns[0]))
+    }
+}
+
+// how much of a file to look at when deciding if it's binary
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+// guesses whether `bytes` is the contents of a binary file the same way grep does, by looking for a
+// NUL byte near the start. text files basically never have one
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_CHECK_LEN).any(|&b| b == 0)
+}
+
+// a line that matched a search, along with where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub line: &'a str,
+    // starts counting at 1, like editors do
+    pub line_number: usize,
+    // where `line` starts in the contents that were searched
+    pub byte_offset: usize,
+    // the part of `line` that matched (as byte indices into `line`)
+    pub span: Range<usize>,
+}
+
+// iterates over the lines of `contents` along with the byte offset each one starts at. lines are
+// split the same way `str::lines` does it, so a trailing "\r\n" or "\n" isn't part of the line
+pub fn lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    contents.split_inclusive('\n').map(move |line| {
+        let start = offset;
+        offset += line.len();
+
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        (start, line)
+    })
+}
+
+// runs `matcher` over every line of `contents`, keeping the lines it finds something in
+fn search_lines<M: Matcher>(contents: &str, matcher: M) -> impl Iterator<Item = Match<'_>> {
+    lines(contents)
+        .enumerate()
+        .filter_map(move |(i, (byte_offset, line))| {
+            matcher.find_at(line, 0).map(|span| Match {
+                line,
+                line_number: i + 1,
+                byte_offset,
+                span,
+            })
+        })
+}
+
 // returns the lines in `contents` that contain `query`
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
+//
+// instead of going line by line, the whole of `contents` is searched at once and the line around
+// each match is only worked out once there is one. lines without a match are never split out or
+// even counted one at a time, which is what most lines are in a big file
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    let mut matches = Vec::new();
+    let found = Searcher::new(Literal::new(query)).search_slice(contents.as_bytes(), |line| {
+        // `contents` is valid UTF-8 already, so the line is just where it is in `contents`
+        let end = line.byte_offset + line.text.len();
+        matches.push(Match {
+            line: &contents[line.byte_offset..end],
+            line_number: line.line_number,
+            byte_offset: line.byte_offset,
+            span: line.spans[0].clone(),
+        });
+        Ok(true)
+    });
+    // nothing is read and nothing above fails, so there's no error it could give back
+    debug_assert!(found.is_ok());
+
+    matches
+}
+
+// returns the lines in `contents` that *don't* contain `query`. nothing matched on these lines so
+// their spans are empty
+pub fn search_inverted<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    lines(contents)
+        .enumerate()
+        .filter(|(_, (_, line))| !line.contains(query))
+        .map(|(i, (byte_offset, line))| Match {
+            line,
+            line_number: i + 1,
+            byte_offset,
+            span: 0..0,
+        })
+        .collect()
+}
+
+// returns at most the first `max` lines in `contents` that contain `query`, without looking any
+// further once it has them
+pub fn search_max<'a>(query: &str, contents: &'a str, max: usize) -> Vec<Match<'a>> {
+    search_lines(contents, Literal::new(query))
+        .take(max)
         .collect()
 }
 
+// returns how many lines in `contents` contain `query`
+pub fn count(query: &str, contents: &str) -> usize {
+    contents.lines().filter(|line| line.contains(query)).count()
+}
+
+// returns the files in `paths` that have a line containing `query`
+pub fn files_with_matches<P: AsRef<Path>>(query: &str, paths: &[P]) -> io::Result<Vec<PathBuf>> {
+    filter_files(query, paths, true)
+}
+
+// returns the files in `paths` that don't have a single line containing `query`
+pub fn files_without_match<P: AsRef<Path>>(query: &str, paths: &[P]) -> io::Result<Vec<PathBuf>> {
+    filter_files(query, paths, false)
+}
+
+// keeps the files in `paths` where whether `query` is in them is `keep`. each file is only read up
+// to its first match
+fn filter_files<P: AsRef<Path>>(query: &str, paths: &[P], keep: bool) -> io::Result<Vec<PathBuf>> {
+    let mut kept = Vec::new();
+
+    for path in paths {
+        let mut found = false;
+        Searcher::new(Literal::new(query)).search(File::open(path)?, |_| {
+            found = true;
+            Ok(false)
+        })?;
+
+        if found == keep {
+            kept.push(path.as_ref().to_path_buf());
+        }
+    }
+
+    Ok(kept)
+}
+
 // returns the lines in `contents` that contain `query` without considering cases
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, CaseInsensitive::new(query)).collect()
+}
 
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
-        .collect()
+// returns the lines in `contents` that match the regex `re`
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, re).collect()
+}
+
+// returns the lines in `contents` that contain any of the patterns `keywords` was built from
+pub fn search_any<'a>(keywords: &AhoCorasick, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, keywords).collect()
+}
+
+// returns the lines in `contents` that have something close enough to `query` in them
+pub fn search_fuzzy<'a>(query: &FuzzyQuery, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, query).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn matched_lines<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.into_iter().map(|m| m.line).collect()
+    }
+
     #[test]
     fn case_sensitive() {
         let query = "duct";
@@ -107,7 +598,29 @@ safe, fast, productive.
 Pick three.
 Duct tape.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec!["safe, fast, productive."],
+            matched_lines(search(query, contents))
+        );
+    }
+
+    #[test]
+    fn search_agrees_with_line_by_line() {
+        let contents = "duct tape\r\nab\r\n\nno\nDUCT duct\n\r\nüduct\nduc\nt\nduct";
+        for query in [
+            "duct",
+            "d",
+            "t",
+            "t\r",
+            "\r",
+            "ü",
+            "uct\n",
+            "",
+            "tape\r\nab",
+        ] {
+            let line_by_line: Vec<Match> = search_lines(contents, Literal::new(query)).collect();
+            assert_eq!(search(query, contents), line_by_line, "{:?}", query);
+        }
     }
 
     #[test]
@@ -122,7 +635,238 @@ Duct tape.";
 
         assert_eq!(
             vec!["Rust:", "Trust Me."],
-            search_case_insensitive(query, contents)
+            matched_lines(search_case_insensitive(query, contents))
         );
     }
+
+    #[test]
+    fn regex() {
+        let re = compile_regex(r"\d{2}:\d{2}", true).unwrap();
+        let contents = "\
+[12:30] req-41 ok
+no timestamp here
+[09:05] req-42 failed";
+
+        assert_eq!(
+            vec!["[12:30] req-41 ok", "[09:05] req-42 failed"],
+            matched_lines(search_regex(&re, contents))
+        );
+    }
+
+    #[test]
+    fn any_of_several() {
+        let keywords = compile_keywords(&["tape", "duct", "duct tape"]).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Duct tape.
+duct tape.";
+
+        let matches = search_any(&keywords, contents);
+        assert_eq!(
+            matched_lines(matches.clone()),
+            vec!["safe, fast, productive.", "Duct tape.", "duct tape."]
+        );
+        assert_eq!(matches[1].span, 5..9);
+        // the longest pattern wins
+        assert_eq!(matches[2].span, 0..9);
+
+        let re = compile_literals(
+            &["tape", "duct", "duct tape", "."],
+            false,
+            Boundary::Any,
+            false,
+        )
+        .unwrap();
+        let matches = search_regex(&re, contents);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[1].span, 0..9);
+
+        let nothing = compile_keywords::<&str>(&[]).unwrap();
+        assert!(search_any(&nothing, contents).is_empty());
+    }
+
+    #[test]
+    fn fuzzy() {
+        let query = FuzzyQuery::new("connection", 1, true).unwrap();
+        let contents = "\
+lost conection to db
+connected...
+reconnected, conection ok";
+
+        let matches = search_fuzzy(&query, contents);
+        assert_eq!(
+            matched_lines(matches.clone()),
+            vec!["lost conection to db", "reconnected, conection ok"]
+        );
+        assert_eq!(matches[0].span, 5..14);
+        assert_eq!(matches[1].span, 13..22);
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let re = compile_regex("^r.st", false).unwrap();
+        let contents = "\
+Rust:
+Trust Me.
+rest";
+
+        assert_eq!(
+            vec!["Rust:", "rest"],
+            matched_lines(search_regex(&re, contents))
+        );
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let err = compile_regex("req-(", true).unwrap_err();
+        assert!(matches!(&err, Error::Regex { pattern, .. } if pattern == "req-("));
+        assert!(err.to_string().contains("req-("));
+    }
+
+    #[test]
+    fn binary() {
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(!is_binary("Rust:\nsafe, fast, productive.".as_bytes()));
+    }
+
+    #[test]
+    fn match_locations() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nPick three.";
+
+        assert_eq!(
+            search("fast", contents),
+            vec![Match {
+                line: "safe, fast, productive.",
+                line_number: 2,
+                byte_offset: 7,
+                span: 6..10,
+            }]
+        );
+    }
+
+    #[test]
+    fn case_insensitive_span() {
+        // 'İ' lowercases to two chars, which shouldn't throw off where the match is
+        let contents = "İstanbul TRUST";
+
+        let matches = search_case_insensitive("trust", contents);
+        assert_eq!(matches[0].span, 10..15);
+        assert_eq!(&contents[matches[0].span.clone()], "TRUST");
+
+        // and neither should chars that fold to something with a different length
+        let contents = "ſtraße STRASSE";
+        let matches = search_case_insensitive("STRAẞE", contents);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].span, 0..8);
+    }
+
+    #[test]
+    fn parallel_output_in_order() {
+        let config = Config::for_test(&["-C1", "x"]).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let stats = Stats::default();
+        sender.send((2, Ok((b"c\n".to_vec(), stats)))).unwrap();
+        sender.send((1, Ok((Vec::new(), stats)))).unwrap();
+        sender.send((0, Ok((b"a\n".to_vec(), stats)))).unwrap();
+        drop(sender);
+
+        let mut out = Vec::new();
+        print_in_order(&config, receiver, &mut out, &mut 0).unwrap();
+        assert_eq!(out, b"a\n--\nc\n");
+    }
+
+    #[test]
+    fn binary_files() {
+        // searches `contents` both as a reader and as a slice, which should print the same
+        let search = |args: &[&str], contents: &[u8]| {
+            let args: Vec<&str> = ["duct"].iter().chain(args).copied().collect();
+            let config = Config::for_test(&args).unwrap();
+            let searcher = Searcher::new(matcher(&config));
+            let path = Path::new("a.out");
+
+            let mut from_reader = Vec::new();
+            let mut printer = Printer::new(&config, false, &mut from_reader);
+            search_input(contents, path, &searcher, config.binary, &mut printer).unwrap();
+            let searches = printer.stats().searches;
+            let mut from_slice = Vec::new();
+            let mut printer = Printer::new(&config, false, &mut from_slice);
+            search_slice(contents, path, &searcher, config.binary, &mut printer).unwrap();
+
+            assert_eq!(from_reader, from_slice);
+            (String::from_utf8(from_reader).unwrap(), searches)
+        };
+        let contents = b"\x7fELF\x02\x01\x01\x00\nduct tape\nglue\nduct\n";
+
+        // skipped files don't count as searched, so `-L` doesn't list them
+        assert_eq!(search(&[], contents), (String::new(), 0));
+        assert_eq!(search(&["-L"], contents), (String::new(), 0));
+        assert_eq!(
+            search(&["--binary", "-n"], contents),
+            (String::from("Binary file a.out matches\n"), 1)
+        );
+        assert_eq!(search(&["--binary", "-c"], contents).0, "2\n");
+        assert_eq!(
+            search(&["--binary", "--json"], contents).0,
+            "{\"type\":\"binary\",\"path\":\"a.out\"}\n"
+        );
+        assert_eq!(search(&["--binary", "glue"], b"\0").0, "");
+        assert_eq!(search(&["-a"], contents).0, "duct tape\nduct\n");
+        assert_eq!(search(&["--binary", "-a"], contents).0, "duct tape\nduct\n");
+
+        // UTF-16 is full of NULs, but it's text
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(
+            "Duct\r\nduct tape\n"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+        assert_eq!(search(&["-n"], &utf16).0, "2:duct tape\n");
+    }
+
+    #[test]
+    fn inverted() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        assert_eq!(
+            vec!["Rust:", "Pick three."],
+            matched_lines(search_inverted("fast", contents))
+        );
+        assert_eq!(search_inverted("fast", contents)[1].line_number, 3);
+    }
+
+    #[test]
+    fn max_and_count() {
+        let contents = "\
+Rust:
+Rust Me.
+Rusty nail.";
+
+        assert_eq!(count("Rust", contents), 3);
+        assert_eq!(
+            vec!["Rust:", "Rust Me."],
+            matched_lines(search_max("Rust", contents, 2))
+        );
+        assert!(search_max("Rust", contents, 0).is_empty());
+    }
+
+    #[test]
+    fn filter_files_by_match() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        std::fs::write(&a, "Rust:\nPick three.").unwrap();
+        std::fs::write(&b, "Duct tape.").unwrap();
+
+        let paths = [&a, &b];
+        let with = files_with_matches("Pick", &paths).unwrap();
+        let without = files_without_match("Pick", &paths).unwrap();
+
+        assert_eq!(with, vec![a]);
+        assert_eq!(without, vec![b]);
+    }
 }
@@ -4,13 +4,18 @@ use std::process;
 use minigrep::Config;
 
 fn main() {
+    // like grep: 0 when something was found, 1 when nothing was, and 2 when something went wrong
     let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        eprintln!("Problem parsing arguments: {}\n\n{}", err, minigrep::USAGE);
+        process::exit(2);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    match minigrep::run(config) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(2);
+        }
     }
 }
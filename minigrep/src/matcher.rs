@@ -0,0 +1,281 @@
+use std::ops::Range;
+
+use aho_corasick::{AhoCorasick, Input};
+use regex::Regex;
+
+use crate::fold::FoldedQuery;
+use crate::fuzzy::FuzzyQuery;
+
+// finds whatever is being searched for in a line. anything that implements this can be handed to
+// a `Searcher`, so searching for something new doesn't need any changes to the rest of minigrep
+pub trait Matcher {
+    // finds the first match at or after the byte offset `start` in `line`, giving back where it
+    // is in the whole line. `start` is always on a char boundary
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>>;
+
+    // finds every match in `line`, none of which overlap
+    fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+
+        while start <= line.len() {
+            let span = match self.find_at(line, start) {
+                Some(span) => span,
+                None => break,
+            };
+            // an empty match would just be found again in the same spot, so step over the next
+            // char
+            start = if span.is_empty() {
+                span.end + line[span.end..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                span.end
+            };
+            spans.push(span);
+        }
+
+        spans
+    }
+
+    // the exact text every match is, if this only ever matches one fixed string. a `Searcher` can
+    // then look for it across a whole buffer at once instead of checking each line
+    fn literal(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        (**self).find_at(line, start)
+    }
+
+    fn literal(&self) -> Option<&str> {
+        (**self).literal()
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        (**self).find_at(line, start)
+    }
+
+    fn literal(&self) -> Option<&str> {
+        (**self).literal()
+    }
+}
+
+// where a match has to start and end to count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    // anywhere at all
+    Any,
+    // at the edges of words, so there's no letter, digit or `_` right before or after the match
+    // (`-w`)
+    Word,
+    // at the start and end of the line, so the match is the whole line (`-x`)
+    Line,
+}
+
+impl Boundary {
+    // wraps the regex `pattern` so it only matches within this boundary
+    pub fn wrap(self, pattern: &str) -> String {
+        match self {
+            Boundary::Any => pattern.to_string(),
+            // the half boundaries only look at the side of the match they're on, so a pattern
+            // that starts or ends with something like `-` still works the way it does with grep
+            Boundary::Word => format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern),
+            Boundary::Line => format!("^(?:{})$", pattern),
+        }
+    }
+}
+
+// only keeps the matches `inner` finds that are within `boundary`. when a match isn't, the search
+// goes on from the char after where it started, so for something like a regex with alternatives
+// a shorter match starting at the same place can be missed. regexes are better off with
+// `Boundary::wrap` instead
+//
+// the line being searched can have line breaks in it (with `-U`), so for `Boundary::Line` a match
+// has to start and end at a line break rather than at the ends of `line`
+pub struct Bounded<M> {
+    inner: M,
+    boundary: Boundary,
+}
+
+impl<M: Matcher> Bounded<M> {
+    pub fn new(inner: M, boundary: Boundary) -> Bounded<M> {
+        Bounded { inner, boundary }
+    }
+
+    // whether `span` in `line` is within the boundary
+    fn within(&self, line: &str, span: &Range<usize>) -> bool {
+        let before = &line[..span.start];
+        let after = &line[span.end..];
+        match self.boundary {
+            Boundary::Any => true,
+            Boundary::Word => {
+                !before.chars().next_back().is_some_and(is_word_char)
+                    && !after.chars().next().is_some_and(is_word_char)
+            }
+            Boundary::Line => {
+                (before.is_empty() || before.ends_with('\n'))
+                    && (after.is_empty() || after.starts_with('\n') || after.starts_with("\r\n"))
+            }
+        }
+    }
+}
+
+impl<M: Matcher> Matcher for Bounded<M> {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let mut from = start;
+        loop {
+            let span = self.inner.find_at(line, from)?;
+            if self.within(line, &span) {
+                return Some(span);
+            }
+            from = span.start + line[span.start..].chars().next()?.len_utf8();
+        }
+    }
+}
+
+// whether `c` is part of a word, the same as `\w` in a regex (near enough: letters and digits from
+// any script, and `_`)
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// a query that's matched exactly as it is
+pub struct Literal {
+    query: String,
+}
+
+impl Literal {
+    pub fn new(query: &str) -> Literal {
+        Literal {
+            query: query.to_string(),
+        }
+    }
+}
+
+impl Matcher for Literal {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        line[start..]
+            .find(&self.query)
+            .map(|i| start + i..start + i + self.query.len())
+    }
+
+    fn literal(&self) -> Option<&str> {
+        Some(&self.query)
+    }
+}
+
+// a query that's matched without considering case
+pub struct CaseInsensitive {
+    query: FoldedQuery,
+}
+
+impl CaseInsensitive {
+    pub fn new(query: &str) -> CaseInsensitive {
+        CaseInsensitive {
+            query: FoldedQuery::new(query),
+        }
+    }
+}
+
+impl Matcher for CaseInsensitive {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.query.find_at(line, start)
+    }
+}
+
+// unlike slicing `line`, searching from `start` still lets things like `^` and `\b` look at what
+// comes before it
+impl Matcher for Regex {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        Regex::find_at(self, line, start).map(|m| m.range())
+    }
+}
+
+// finds the first (and longest, if more than one starts there) of the patterns the automaton was
+// built from
+impl Matcher for AhoCorasick {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        self.find(Input::new(line).range(start..))
+            .map(|m| m.range())
+    }
+}
+
+impl Matcher for FuzzyQuery {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        FuzzyQuery::find_at(self, line, start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_regex;
+
+    #[test]
+    fn all_spans() {
+        let re = compile_regex(r"^a|b", true).unwrap();
+        assert_eq!(re.find_all("aab ab"), vec![0..1, 2..3, 5..6]);
+        assert_eq!(
+            CaseInsensitive::new("ab").find_all("AB ab"),
+            vec![0..2, 3..5]
+        );
+        // empty matches shouldn't get stuck
+        assert_eq!(Literal::new("").find_all("añ"), vec![0..0, 1..1, 3..3]);
+    }
+
+    #[test]
+    fn words() {
+        let id = Bounded::new(Literal::new("id"), Boundary::Word);
+        assert_eq!(
+            id.find_all("width id hidden (id) _id id_ id"),
+            vec![6..8, 17..19, 29..31]
+        );
+        assert_eq!(id.find_at("idid", 0), None);
+
+        // letters outside of ASCII count as part of a word too
+        let cafe = Bounded::new(CaseInsensitive::new("CAF"), Boundary::Word);
+        assert_eq!(cafe.find_at("café caf", 0), Some(6..9));
+
+        let re = Regex::new(&Boundary::Word.wrap("foo|foobar|-x")).unwrap();
+        assert_eq!(re.find_all("foobar foo -x a-x"), vec![0..6, 7..10, 11..13]);
+    }
+
+    #[test]
+    fn lines() {
+        let line = Bounded::new(CaseInsensitive::new("duct tape"), Boundary::Line);
+        assert_eq!(line.find_at("Duct Tape", 0), Some(0..9));
+        assert_eq!(line.find_at("duct tape.", 0), None);
+        assert_eq!(line.find_at("duct tape", 1), None);
+        assert_eq!(
+            Bounded::new(Literal::new(""), Boundary::Line).find_all(""),
+            vec![0..0]
+        );
+
+        // with `-U`, whole lines are the ones between line breaks
+        let tape = Bounded::new(Literal::new("tape"), Boundary::Line);
+        assert_eq!(
+            tape.find_all("duct tape\ntape\r\ntapes\ntape"),
+            vec![10..14, 22..26]
+        );
+
+        let re = Regex::new(&Boundary::Line.wrap("a|ab")).unwrap();
+        assert_eq!(re.find_at("ab", 0).map(|m| m.range()), Some(0..2));
+    }
+
+    #[test]
+    fn trait_objects() {
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(Literal::new("tape")),
+            Box::new(CaseInsensitive::new("TAPE")),
+            Box::new(compile_regex("t.pe", true).unwrap()),
+            Box::new(FuzzyQuery::new("tape", 1, true).unwrap()),
+        ];
+        for matcher in &matchers {
+            assert_eq!(matcher.find_at("duct tape", 0), Some(5..9));
+            assert_eq!(matcher.find_all("tape, tape"), vec![0..4, 6..10]);
+        }
+    }
+}
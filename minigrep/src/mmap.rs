@@ -0,0 +1,48 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+
+// files smaller than this are read the normal way, since setting up a mapping costs more than
+// copying that little bit through a buffer does
+const MIN_LEN: u64 = 1024 * 1024;
+
+// memory maps `file` so it can be searched in place, if that looks like it'd be faster than
+// reading it. only regular files big enough to be worth it are mapped, since pipes, sockets and
+// devices either can't be mapped or don't have a size to go on. gives back None whenever the file
+// should just be read instead, which includes when mapping it fails
+pub fn map(file: &File) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() < MIN_LEN {
+        return None;
+    }
+
+    // safety: the mapping is only ever read from. the catch is that if some other process
+    // truncates the file while it's being searched, reading the part that's gone crashes us with
+    // SIGBUS. grep and ripgrep make the same trade, and `--no-mmap` is there to avoid it
+    unsafe { Mmap::map(file) }.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn only_big_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let small = dir.join("small.txt");
+        fs::write(&small, "duct tape\n").unwrap();
+        let big = dir.join("big.txt");
+        let contents = "duct tape\n".repeat(MIN_LEN as usize / 10 + 1);
+        fs::write(&big, &contents).unwrap();
+
+        let small_map = map(&File::open(&small).unwrap());
+        let big_map = map(&File::open(&big).unwrap());
+        let dir_map = map(&File::open(dir).unwrap());
+
+        assert!(small_map.is_none());
+        assert_eq!(&big_map.unwrap()[..], contents.as_bytes());
+        assert!(dir_map.is_none());
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::replace::replace_spans;
+use crate::{Color, Config, Line, Mode};
+
+// the ANSI escape codes used for `--color`, picked to look like grep's
+const MATCH_STYLE: &str = "\x1b[1;31m";
+const PATH_STYLE: &str = "\x1b[35m";
+const NUMBER_STYLE: &str = "\x1b[32m";
+const SEPARATOR_STYLE: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+// counts of what's been found so far, for the summary at the end of `--json` output
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    // how many files (or stdin) were searched
+    pub searches: usize,
+    // how many of those had at least one match
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+    // every match on every line, so a line with two matches counts twice
+    pub matches: usize,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+
+    // whether the search found what it was looking for, which is what decides the exit code. with
+    // `-L` that's a file without any matches
+    pub fn found(&self, mode: Mode) -> bool {
+        match mode {
+            Mode::FilesWithoutMatch => self.searches > self.searches_with_match,
+            _ => self.searches_with_match > 0,
+        }
+    }
+}
+
+// prints matches the way grep does: `path:line number:byte offset:line`, where each of the parts
+// before the line only shows up if it was asked for. context lines use `-` instead of `:` and
+// groups of lines that aren't next to each other get a `--` line between them
+//
+// lines are handed over one at a time as they're read, so only the last few lines (for
+// `-B`) are ever held on to
+pub struct Printer<'a, W: Write> {
+    config: &'a Config,
+    with_path: bool,
+    color: bool,
+    out: W,
+    // whether a line has been printed yet, to know if a separator is needed before the next group
+    // of context (even if it's in a different file)
+    printed_any: bool,
+    stats: Stats,
+
+    // everything below is for the file that's being printed right now
+    path: PathBuf,
+    // whether it's binary, so all that's printed is whether it matched (`--binary`)
+    binary: bool,
+    count: usize,
+    // how many of those a match started on, which is what `-m` counts. in multiline mode a match
+    // can carry on over the lines after the one it started on, and those are still printed as
+    // part of it
+    started: usize,
+    // the line number of the last line that was printed
+    last_printed: Option<usize>,
+    // lines that might be printed as context before the next match
+    before: VecDeque<(usize, usize, String)>,
+    // how many more lines should be printed as context after the last match
+    after_left: usize,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    pub fn new(config: &'a Config, with_path: bool, out: W) -> Printer<'a, W> {
+        Printer {
+            config,
+            with_path,
+            color: config.color == Color::Always,
+            out,
+            printed_any: false,
+            stats: Stats::default(),
+            path: PathBuf::new(),
+            binary: false,
+            count: 0,
+            started: 0,
+            last_printed: None,
+            before: VecDeque::with_capacity(config.before_context),
+            after_left: 0,
+        }
+    }
+
+    // gets ready to print the lines from the file at `path`
+    pub fn begin(&mut self, path: &Path) {
+        self.path = path.to_path_buf();
+        self.binary = false;
+        self.count = 0;
+        self.started = 0;
+        self.last_printed = None;
+        self.before.clear();
+        self.after_left = 0;
+        self.stats.searches += 1;
+    }
+
+    // takes back the `begin` for a file that turned out to be binary and won't be searched after
+    // all, so it isn't counted in the stats
+    pub fn skip(&mut self) {
+        self.stats.searches -= 1;
+    }
+
+    // says that the current file is binary, so instead of printing its lines it just prints
+    // whether it matched. called after `begin`
+    pub fn binary(&mut self) {
+        self.binary = true;
+    }
+
+    // takes the next line of the current file. returns whether there's any point in reading more
+    // lines from it
+    pub fn line(&mut self, line: &Line) -> io::Result<bool> {
+        let continues_match = line.continued && !self.config.invert_match;
+        if self.config.max_count.is_some_and(|max| self.started >= max) && !continues_match {
+            // past `-m`, all that's left to do is print the context after the last match (and
+            // what would've been a match is just context now)
+            if self.after_left == 0 || !matches!(self.config.mode, Mode::Lines | Mode::Json) {
+                return Ok(false);
+            }
+            self.after_left -= 1;
+            self.print_line(line.line_number, line.byte_offset, line.text, &[], '-')?;
+            return Ok(self.after_left > 0);
+        }
+
+        let matched = line.spans.is_empty() == self.config.invert_match;
+        if matched {
+            self.count += 1;
+            if !continues_match || line.spans.len() > 1 {
+                self.started += 1;
+            }
+            self.stats.matched_lines += 1;
+            // a match over several lines is only counted on the first of them
+            self.stats.matches += line.spans.len() - usize::from(line.continued);
+        }
+
+        match self.config.mode {
+            Mode::Lines | Mode::Json => {}
+            Mode::Count => return Ok(true),
+            Mode::FilesWithMatches => {
+                if matched {
+                    paint(&mut self.out, self.color, PATH_STYLE, self.path.display())?;
+                    writeln!(self.out)?;
+                }
+                return Ok(!matched);
+            }
+            // whether anything matched is all that matters, `end` takes care of the printing
+            Mode::FilesWithoutMatch => return Ok(!matched),
+        }
+
+        // a binary file's lines would be gibberish (and there's no context to print either), so
+        // the first match is as far as it goes
+        if self.binary {
+            if matched {
+                self.print_binary()?;
+            }
+            return Ok(!matched);
+        }
+
+        if matched {
+            while let Some((line_number, byte_offset, text)) = self.before.pop_front() {
+                self.print_line(line_number, byte_offset, &text, &[], '-')?;
+            }
+            self.print_line(
+                line.line_number,
+                line.byte_offset,
+                line.text,
+                &line.spans,
+                ':',
+            )?;
+            self.after_left = self.config.after_context;
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            self.print_line(line.line_number, line.byte_offset, line.text, &[], '-')?;
+        } else if self.config.before_context > 0 {
+            if self.before.len() == self.config.before_context {
+                self.before.pop_front();
+            }
+            self.before
+                .push_back((line.line_number, line.byte_offset, line.text.to_string()));
+        }
+
+        Ok(true)
+    }
+
+    // whether lines without a match need to be handed over too, for context or `-v`. if not, the
+    // search can skip straight past them
+    pub fn wants_every_line(&self) -> bool {
+        self.config.invert_match || self.config.before_context > 0 || self.config.after_context > 0
+    }
+
+    // whether there's no point handing over any more lines of the current file, since `-m` has
+    // been reached and there's no context left to print after the last match
+    pub fn finished(&self) -> bool {
+        self.config.max_count.is_some_and(|max| self.started >= max)
+            && (self.after_left == 0 || !matches!(self.config.mode, Mode::Lines | Mode::Json))
+    }
+
+    // called once every line of the current file has been handed over
+    pub fn end(&mut self) -> io::Result<()> {
+        if self.count > 0 {
+            self.stats.searches_with_match += 1;
+        }
+
+        match self.config.mode {
+            Mode::Count if self.with_path => {
+                paint(&mut self.out, self.color, PATH_STYLE, self.path.display())?;
+                paint(&mut self.out, self.color, SEPARATOR_STYLE, ':')?;
+                writeln!(self.out, "{}", self.count)?;
+            }
+            Mode::Count => writeln!(self.out, "{}", self.count)?,
+            Mode::FilesWithoutMatch if self.count == 0 => {
+                paint(&mut self.out, self.color, PATH_STYLE, self.path.display())?;
+                writeln!(self.out)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    // called once every file has been printed
+    pub fn summary(&mut self) -> io::Result<()> {
+        if self.config.mode == Mode::Json {
+            print_summary(&mut self.out, &self.stats)?;
+        }
+        Ok(())
+    }
+
+    fn print_line(
+        &mut self,
+        line_number: usize,
+        byte_offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+        sep: char,
+    ) -> io::Result<()> {
+        if self.config.mode == Mode::Json {
+            return self.print_json_line(line_number, byte_offset, text, spans, sep);
+        }
+
+        let follows_last =
+            self.last_printed.is_some() && self.last_printed == line_number.checked_sub(1);
+        if uses_separators(self.config) && self.printed_any && !follows_last {
+            paint(&mut self.out, self.color, SEPARATOR_STYLE, "--")?;
+            writeln!(self.out)?;
+        }
+        self.printed_any = true;
+        self.last_printed = Some(line_number);
+
+        let color = self.color;
+        if self.with_path {
+            paint(&mut self.out, color, PATH_STYLE, self.path.display())?;
+            paint(&mut self.out, color, SEPARATOR_STYLE, sep)?;
+        }
+        if self.config.line_number {
+            paint(&mut self.out, color, NUMBER_STYLE, line_number)?;
+            paint(&mut self.out, color, SEPARATOR_STYLE, sep)?;
+        }
+        if self.config.byte_offset {
+            paint(&mut self.out, color, NUMBER_STYLE, byte_offset)?;
+            paint(&mut self.out, color, SEPARATOR_STYLE, sep)?;
+        }
+
+        // with `-r`, it's the line with the replacements made that's printed (and highlighted)
+        let replaced;
+        let (text, spans) = match &self.config.replace {
+            Some(replacement) if !spans.is_empty() => {
+                replaced = replace_spans(text, spans, self.config.regex.as_ref(), replacement);
+                (replaced.0.as_str(), replaced.1.as_slice())
+            }
+            _ => (text, spans),
+        };
+
+        if !color {
+            return writeln!(self.out, "{}", text);
+        }
+        let mut last = 0;
+        for span in spans.iter().filter(|span| !span.is_empty()) {
+            write!(self.out, "{}", &text[last..span.start])?;
+            paint(&mut self.out, color, MATCH_STYLE, &text[span.clone()])?;
+            last = span.end;
+        }
+        writeln!(self.out, "{}", &text[last..])
+    }
+
+    // prints that the current file, which is binary, matched. like grep, this doesn't get a path
+    // or a line number in front of it
+    fn print_binary(&mut self) -> io::Result<()> {
+        if self.config.mode == Mode::Json {
+            return writeln!(
+                self.out,
+                "{{\"type\":\"binary\",\"path\":{}}}",
+                json_string(&self.path.display().to_string())
+            );
+        }
+
+        let path = self.path.display();
+        if !self.color {
+            return writeln!(self.out, "Binary file {} matches", path);
+        }
+        write!(self.out, "Binary file ")?;
+        paint(&mut self.out, true, PATH_STYLE, path)?;
+        writeln!(self.out, " matches")
+    }
+
+    // prints a line as a JSON object on a line of its own, with a type of "match" or "context".
+    // every field is always there, no matter what `-n`/`-b` say, so tools reading it don't have
+    // to guess
+    fn print_json_line(
+        &mut self,
+        line_number: usize,
+        byte_offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+        sep: char,
+    ) -> io::Result<()> {
+        let kind = if sep == ':' { "match" } else { "context" };
+        let spans: Vec<String> = spans
+            .iter()
+            .map(|span| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"text\":{}}}",
+                    span.start,
+                    span.end,
+                    json_string(&text[span.clone()])
+                )
+            })
+            .collect();
+
+        writeln!(
+            self.out,
+            "{{\"type\":\"{}\",\"path\":{},\"line_number\":{},\"byte_offset\":{},\"line\":{},\"spans\":[{}]}}",
+            kind,
+            json_string(&self.path.display().to_string()),
+            line_number,
+            byte_offset,
+            json_string(text),
+            spans.join(",")
+        )
+    }
+}
+
+// writes `text` in `style` if `color` is on, or as it is if it isn't
+fn paint<W: Write>(out: &mut W, color: bool, style: &str, text: impl Display) -> io::Result<()> {
+    if color {
+        write!(out, "{}{}{}", style, text, RESET)
+    } else {
+        write!(out, "{}", text)
+    }
+}
+
+// what `--color=auto` means for stdout: color only when it's a terminal and NO_COLOR isn't set to
+// anything (see https://no-color.org)
+pub fn color_stdout() -> bool {
+    env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && io::stdout().is_terminal()
+}
+
+// whether groups of context get a `--` line between them
+pub fn uses_separators(config: &Config) -> bool {
+    config.mode == Mode::Lines && (config.before_context > 0 || config.after_context > 0)
+}
+
+// prints the record that ends `--json` output
+pub fn print_summary<W: Write>(out: &mut W, stats: &Stats) -> io::Result<()> {
+    writeln!(
+        out,
+        "{{\"type\":\"summary\",\"searches\":{},\"searches_with_match\":{},\"matched_lines\":{},\"matches\":{}}}",
+        stats.searches, stats.searches_with_match, stats.matched_lines, stats.matches
+    )
+}
+
+// quotes `s` as a JSON string, escaping whatever needs it
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{matcher, Searcher};
+
+    // runs `query` over `contents` and gives back what would've been printed
+    fn print(args: &[&str], contents: &str) -> String {
+        let config = Config::for_test(args).unwrap();
+
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, false, &mut out);
+        printer.begin(Path::new("test.txt"));
+        Searcher::new(matcher(&config))
+            .multiline(config.multiline)
+            .search_lines(contents.as_bytes(), |line| printer.line(&line))
+            .unwrap();
+        printer.end().unwrap();
+        printer.summary().unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn context_groups_merge_when_touching() {
+        let contents = "0\n1 x\n2\n3\n4 x\n5\n6\n7\n8\n9 x\n10\n11\n";
+
+        assert_eq!(
+            print(&["-n", "-C1", "x", "test.txt"], contents),
+            "1-0\n2:1 x\n3-2\n4-3\n5:4 x\n6-5\n--\n9-8\n10:9 x\n11-10\n"
+        );
+    }
+
+    #[test]
+    fn context_stays_in_bounds() {
+        let contents = "0 x\n1\n2\n3\n4\n5\n6\n7 x\n8";
+
+        assert_eq!(
+            print(&["-B2", "-A3", "x", "test.txt"], contents),
+            "0 x\n1\n2\n3\n--\n5\n6\n7 x\n8\n"
+        );
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(print(&["-c", "x", "test.txt"], "x\ny\nxx\n"), "2\n");
+        assert_eq!(print(&["-cv", "x", "test.txt"], "x\ny\nxx\n"), "1\n");
+    }
+
+    #[test]
+    fn max_count() {
+        let contents = "x 1\nx 2\ny\nx 3\nz\n";
+
+        assert_eq!(print(&["-m2", "x", "test.txt"], contents), "x 1\nx 2\n");
+        assert_eq!(
+            print(&["-m1", "-A2", "x", "test.txt"], contents),
+            "x 1\nx 2\ny\n"
+        );
+        assert_eq!(print(&["-m0", "x", "test.txt"], contents), "");
+        assert_eq!(print(&["-c", "-m2", "x", "test.txt"], contents), "2\n");
+    }
+
+    #[test]
+    fn files_with_and_without_match() {
+        assert_eq!(print(&["-l", "x", "test.txt"], "x\nx\n"), "test.txt\n");
+        assert_eq!(print(&["-L", "x", "test.txt"], "x\nx\n"), "");
+        assert_eq!(print(&["-L", "x", "test.txt"], "y\n"), "test.txt\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            print(&["--json", "-A1", "x", "test.txt"], "a \"x\" x\n\tb\nc\n"),
+            "\
+{\"type\":\"match\",\"path\":\"test.txt\",\"line_number\":1,\"byte_offset\":0,\"line\":\"a \\\"x\\\" x\",\"spans\":[{\"start\":3,\"end\":4,\"text\":\"x\"},{\"start\":6,\"end\":7,\"text\":\"x\"}]}
+{\"type\":\"context\",\"path\":\"test.txt\",\"line_number\":2,\"byte_offset\":8,\"line\":\"\\tb\",\"spans\":[]}
+{\"type\":\"summary\",\"searches\":1,\"searches_with_match\":1,\"matched_lines\":1,\"matches\":2}
+"
+        );
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(
+            print(&["--color=always", "-n", "x", "test.txt"], "a x x\n"),
+            "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0ma \x1b[1;31mx\x1b[0m \x1b[1;31mx\x1b[0m\n"
+        );
+        assert_eq!(
+            print(&["--color=never", "-n", "x", "test.txt"], "a x x\n"),
+            "1:a x x\n"
+        );
+        // there's nothing to highlight on the lines `-v` prints
+        assert_eq!(
+            print(&["--color=always", "-v", "x", "test.txt"], "x\ny\n"),
+            "y\n"
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let contents = "duct tape\nglue\n";
+
+        assert_eq!(
+            print(&["-r", "sticky", "-A1", "duct", "test.txt"], contents),
+            "sticky tape\nglue\n"
+        );
+        assert_eq!(
+            print(
+                &["-E", "-r", "<$0>", "--color=always", "[a-z]+", "test.txt"],
+                contents
+            ),
+            "\x1b[1;31m<duct>\x1b[0m \x1b[1;31m<tape>\x1b[0m\n\x1b[1;31m<glue>\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn multiline() {
+        let contents = "fn main() {\n    run();\n}\n\nfn run() {}\n";
+
+        assert_eq!(
+            print(&["-Un", r"main() {\n    run", "test.txt"], contents),
+            "1:fn main() {\n2:    run();\n"
+        );
+        assert_eq!(
+            print(&["-Un", "-A1", "-E", r"\{\n.*?\}", "test.txt"], contents),
+            "1:fn main() {\n2:    run();\n3:}\n4-\n"
+        );
+        assert_eq!(
+            print(&["-Uc", "-x", r"}\n\nfn run() {}", "test.txt"], contents),
+            "3\n"
+        );
+        // `-m` counts the matches, and the lines each one carries on over are still printed
+        assert_eq!(
+            print(&["-Un", "-m1", "-E", r"\{\n.*?\}", "test.txt"], contents),
+            "1:fn main() {\n2:    run();\n3:}\n"
+        );
+        assert_eq!(
+            print(&["-U", "-m1", "-A1", r"a\nb", "test.txt"], "a\nb\na\nb\n"),
+            "a\nb\na\n"
+        );
+        assert_eq!(
+            print(&["--json", "-U", r"{\n  ", "test.txt"], contents),
+            "\
+{\"type\":\"match\",\"path\":\"test.txt\",\"line_number\":1,\"byte_offset\":0,\"line\":\"fn main() {\",\"spans\":[{\"start\":10,\"end\":11,\"text\":\"{\"}]}
+{\"type\":\"match\",\"path\":\"test.txt\",\"line_number\":2,\"byte_offset\":12,\"line\":\"    run();\",\"spans\":[{\"start\":0,\"end\":2,\"text\":\"  \"}]}
+{\"type\":\"summary\",\"searches\":1,\"searches_with_match\":1,\"matched_lines\":2,\"matches\":1}
+"
+        );
+    }
+
+    #[test]
+    fn json_escapes() {
+        assert_eq!(json_string("a\\b\u{1}é"), "\"a\\\\b\\u0001é\"");
+    }
+}
@@ -0,0 +1,206 @@
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use regex::Regex;
+use tempfile::NamedTempFile;
+
+use crate::error::with_path;
+use crate::{is_binary, lines, Config, Matcher};
+
+// swaps every span in `line` for `replacement`, giving back the new line along with where each
+// replacement ended up in it. with a regex, `$1`, `$name` and `${name}` in `replacement` are filled
+// in from the capture groups of each match (and `$$` is a plain `$`)
+pub fn replace_spans(
+    line: &str,
+    spans: &[Range<usize>],
+    re: Option<&Regex>,
+    replacement: &str,
+) -> (String, Vec<Range<usize>>) {
+    let mut replaced = String::with_capacity(line.len());
+    let mut new_spans = Vec::with_capacity(spans.len());
+    let mut last = 0;
+
+    for span in spans {
+        replaced.push_str(&line[last..span.start]);
+        let start = replaced.len();
+        // searching from where the match starts finds that same match again, this time with its
+        // capture groups
+        match re.and_then(|re| re.captures_at(line, span.start)) {
+            Some(captures) => captures.expand(replacement, &mut replaced),
+            None => replaced.push_str(replacement),
+        }
+        new_spans.push(start..replaced.len());
+        last = span.end;
+    }
+    replaced.push_str(&line[last..]);
+
+    (replaced, new_spans)
+}
+
+// a line that `--in-place` would change
+struct Change {
+    line_number: usize,
+    old: String,
+    new: String,
+}
+
+// replaces the matches in the file at `path` with `config.replace`. the new contents go into a
+// temporary file next to it that's then renamed over it, so the file is never left half written.
+// with `--dry-run` nothing is written, and what would've changed is printed to `out` as a diff
+// instead
+//
+// binary files and files that aren't UTF-8 are left alone, since there's no telling what replacing
+// text in them would do
+//
+// gives back whether anything in the file matched
+pub fn edit_file<M, W>(config: &Config, path: &Path, matcher: M, out: &mut W) -> io::Result<bool>
+where
+    M: Matcher,
+    W: Write,
+{
+    let with_path = |e| with_path(path, e);
+
+    let bytes = fs::read(path).map_err(with_path)?;
+    if is_binary(&bytes) {
+        return Ok(false);
+    }
+    let contents = match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(_) => {
+            eprintln!(
+                "minigrep: {}: not valid UTF-8, leaving it alone",
+                path.display()
+            );
+            return Ok(false);
+        }
+    };
+
+    let replacement = config.replace.as_deref().unwrap_or("");
+    let mut edited = String::with_capacity(contents.len());
+    let mut changes = Vec::new();
+    let mut matched_lines = 0;
+
+    for (i, (offset, line)) in lines(&contents).enumerate() {
+        // whatever ended the line ("\n", "\r\n", or nothing at the very end) is kept as it was
+        let rest = &contents[offset + line.len()..];
+        let ending = &rest[..rest.find('\n').map_or(rest.len(), |i| i + 1)];
+
+        let spans = if config.max_count.is_some_and(|max| matched_lines >= max) {
+            Vec::new()
+        } else {
+            matcher.find_all(line)
+        };
+        if spans.is_empty() {
+            edited.push_str(line);
+        } else {
+            matched_lines += 1;
+            let (new, _) = replace_spans(line, &spans, config.regex.as_ref(), replacement);
+            edited.push_str(&new);
+            if new != line {
+                changes.push(Change {
+                    line_number: i + 1,
+                    old: line.to_string(),
+                    new,
+                });
+            }
+        }
+        edited.push_str(ending);
+    }
+
+    if changes.is_empty() {
+        return Ok(matched_lines > 0);
+    }
+    if config.dry_run {
+        print_diff(path, &changes, out)?;
+        return Ok(true);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let permissions = fs::metadata(path).map_err(with_path)?.permissions();
+    let mut temp = NamedTempFile::new_in(dir).map_err(with_path)?;
+    temp.write_all(edited.as_bytes()).map_err(with_path)?;
+    temp.as_file()
+        .set_permissions(permissions)
+        .map_err(with_path)?;
+    temp.as_file().sync_all().map_err(with_path)?;
+    temp.persist(path).map_err(|e| with_path(e.error))?;
+
+    Ok(true)
+}
+
+// prints `changes` as a unified diff, with each changed line as a hunk of its own
+fn print_diff<W: Write>(path: &Path, changes: &[Change], out: &mut W) -> io::Result<()> {
+    writeln!(out, "--- {}", path.display())?;
+    writeln!(out, "+++ {}", path.display())?;
+    for change in changes {
+        writeln!(out, "@@ -{0} +{0} @@", change.line_number)?;
+        writeln!(out, "-{}", change.old)?;
+        writeln!(out, "+{}", change.new)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_regex, matcher};
+
+    fn config(args: &[&str]) -> Config {
+        Config::for_test(args).unwrap()
+    }
+
+    #[test]
+    fn literal() {
+        let (line, spans) = replace_spans("a duct, a tape", &[2..6, 10..14], None, "$1 roll");
+        assert_eq!(line, "a $1 roll, a $1 roll");
+        assert_eq!(spans, vec![2..9, 13..20]);
+    }
+
+    #[test]
+    fn capture_groups() {
+        let re = compile_regex(r"(\w+)@(?P<host>\w+)", true).unwrap();
+        let (line, spans) = replace_spans(
+            "to: me@home, you@work",
+            &[4..11, 13..21],
+            Some(&re),
+            "${host}:$1",
+        );
+        assert_eq!(line, "to: home:me, work:you");
+        assert_eq!(spans, vec![4..11, 13..21]);
+    }
+
+    #[test]
+    fn in_place() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("poem.txt");
+        let contents = "duct tape\r\nnothing\nduct, duct\n$ duct";
+        fs::write(&path, contents).unwrap();
+        let path_arg = path.to_str().unwrap();
+
+        let mut out = Vec::new();
+        let dry_run = config(&["-r", "glue", "--in-place", "--dry-run", "duct", path_arg]);
+        assert!(edit_file(&dry_run, &path, matcher(&dry_run), &mut out).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "--- {0}\n+++ {0}\n@@ -1 +1 @@\n-duct tape\n+glue tape\n@@ -3 +3 @@\n-duct, duct\n+glue, glue\n@@ -4 +4 @@\n-$ duct\n+$ glue\n",
+                path.display()
+            )
+        );
+
+        let mut out = Vec::new();
+        let edit = config(&["-E", "-m2", "-r", "[$1]", "--in-place", "(d)uct", path_arg]);
+        assert!(edit_file(&edit, &path, matcher(&edit), &mut out).unwrap());
+        let edited = fs::read_to_string(&path).unwrap();
+
+        assert!(out.is_empty());
+        assert_eq!(edited, "[d] tape\r\nnothing\n[d], [d]\n$ duct");
+    }
+}
@@ -0,0 +1,443 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+
+use crate::matcher::Matcher;
+use crate::substring::Horspool;
+
+// a line read by a `Searcher`, along with where it was found. the line has been converted to UTF-8
+// (lossily, so anything invalid shows up as U+FFFD) and doesn't include its line ending
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    // starts counting at 1, like editors do
+    pub line_number: usize,
+    // where the line starts in the stream, counted in the bytes that were actually read
+    pub byte_offset: usize,
+    // every part of `text` that matched, in order. empty if nothing did
+    pub spans: Vec<Range<usize>>,
+    // whether the first span is the rest of a match that started on a line before this one, which
+    // only happens in multiline mode
+    pub continued: bool,
+}
+
+// runs a `Matcher` over anything that can be read, one line at a time. only one line is held in
+// memory at once so this works on inputs of any size, unless it's searching in multiline mode
+pub struct Searcher<M> {
+    matcher: M,
+    multiline: bool,
+}
+
+impl<M: Matcher> Searcher<M> {
+    pub fn new(matcher: M) -> Searcher<M> {
+        Searcher {
+            matcher,
+            multiline: false,
+        }
+    }
+
+    // whether to run the matcher over the whole input at once instead of one line at a time, so
+    // a match can run over more than one line (`-U`). the whole input has to be read into memory
+    // for that, if it isn't there already
+    pub fn multiline(mut self, multiline: bool) -> Searcher<M> {
+        self.multiline = multiline;
+        self
+    }
+
+    // hands each line of `reader` with a match in it to `sink`, stopping early if `sink` returns
+    // false
+    pub fn search<R, S>(&self, reader: R, mut sink: S) -> io::Result<()>
+    where
+        R: Read,
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        self.search_lines(reader, |line| {
+            if line.spans.is_empty() {
+                return Ok(true);
+            }
+            sink(line)
+        })
+    }
+
+    // hands every line of `reader` to `sink`, whether it has a match in it or not (the ones that
+    // don't just have no spans), stopping early if `sink` returns false. this is what printing
+    // context around matches or printing the lines that don't match needs
+    pub fn search_lines<R, S>(&self, reader: R, mut sink: S) -> io::Result<()>
+    where
+        R: Read,
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        if self.multiline {
+            reader.read_to_end(&mut buf)?;
+            return self.search_slice_lines(&buf, sink);
+        }
+        let mut line_number = 0;
+        let mut byte_offset = 0;
+
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                return Ok(());
+            }
+            line_number += 1;
+
+            if !self.line(&buf, line_number, byte_offset, &mut sink)? {
+                return Ok(());
+            }
+            byte_offset += read;
+        }
+    }
+
+    // like `search_lines`, but for input that's all in memory already (like a memory mapped file).
+    // lines are searched where they are in `bytes`, without being copied anywhere first
+    pub fn search_slice_lines<S>(&self, bytes: &[u8], mut sink: S) -> io::Result<()>
+    where
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        if self.multiline {
+            return self.search_multiline(bytes, sink);
+        }
+        let mut line_number = 0;
+        let mut byte_offset = 0;
+
+        while byte_offset < bytes.len() {
+            let rest = &bytes[byte_offset..];
+            let len = memchr::memchr(b'\n', rest).map_or(rest.len(), |i| i + 1);
+            line_number += 1;
+
+            if !self.line(&rest[..len], line_number, byte_offset, &mut sink)? {
+                return Ok(());
+            }
+            byte_offset += len;
+        }
+
+        Ok(())
+    }
+
+    // like `search`, but for input that's all in memory already. when the matcher is looking for
+    // a fixed string, the whole of `bytes` is scanned for it at once and only the lines it turns up
+    // on are looked at, which skips past lines without a match much faster than checking each one
+    pub fn search_slice<S>(&self, bytes: &[u8], mut sink: S) -> io::Result<()>
+    where
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        let mut only_matches = |line: Line| {
+            if line.spans.is_empty() {
+                return Ok(true);
+            }
+            sink(line)
+        };
+        // a line can't have a newline in it and an empty query is on every line anyway. a U+FFFD
+        // could be matching something invalid, which isn't in `bytes` as it is
+        let needle = match self.matcher.literal() {
+            Some(query)
+                if !self.multiline && !query.is_empty() && !query.contains(['\n', '\u{fffd}']) =>
+            {
+                Horspool::new(query.as_bytes())
+            }
+            _ => return self.search_slice_lines(bytes, only_matches),
+        };
+        // the number of the line that starts at `counted_to`
+        let mut line_number = 1;
+        let mut counted_to = 0;
+        let mut start = 0;
+
+        while let Some(hit) = needle.find_at(bytes, start) {
+            let line_start = memchr::memrchr(b'\n', &bytes[..hit]).map_or(0, |i| i + 1);
+            let line_end =
+                memchr::memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |i| hit + i + 1);
+            line_number += memchr::memchr_iter(b'\n', &bytes[counted_to..line_start]).count();
+            counted_to = line_start;
+
+            // the line's matches are found again the usual way, which also drops it if all that
+            // was found runs into the "\r" of a "\r\n"
+            let line = &bytes[line_start..line_end];
+            if !self.line(line, line_number, line_start, &mut only_matches)? {
+                return Ok(());
+            }
+            start = line_end;
+        }
+
+        Ok(())
+    }
+
+    // runs the matcher over all of `bytes` at once and then hands every line to `sink`, the same
+    // as `search_slice_lines` does. a line's spans are the parts of any matches that are on it, so
+    // a match over three lines shows up on each of them
+    fn search_multiline<S>(&self, bytes: &[u8], mut sink: S) -> io::Result<()>
+    where
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        // decoding can change how long the lines are but not where they end, since a newline is
+        // never part of what gets replaced. so the lines are found in both, to still be able to
+        // give back offsets in the bytes that were read
+        let text = String::from_utf8_lossy(bytes);
+        let matches = self.matcher.find_all(&text);
+        // the first match that might still be on the line being looked at
+        let mut first = 0;
+        let mut line_number = 0;
+        let mut byte_offset = 0;
+        let mut start = 0;
+
+        while start < text.len() {
+            let rest = &text[start..];
+            let next = start + memchr::memchr(b'\n', rest.as_bytes()).map_or(rest.len(), |i| i + 1);
+            let line = trim_line_ending(text[start..next].as_bytes());
+            let end = start + line.len();
+            line_number += 1;
+
+            // a match that ends where this line starts is all on the lines before it, unless
+            // it's an empty match right at the start of this one
+            while matches
+                .get(first)
+                .is_some_and(|m| m.end < start || (m.end == start && !m.is_empty()))
+            {
+                first += 1;
+            }
+            let spans = matches[first..]
+                .iter()
+                // (a line without a line ending is the last one, so an empty match at its end
+                // is on it too)
+                .take_while(|m| m.start < next || m.start == end)
+                // an empty match in the line ending isn't on the line, where one that's more than
+                // that still is even if all it's matched on this line is the line break
+                .filter(|m| !m.is_empty() || m.start <= end)
+                .map(|m| {
+                    let span_start = m.start.clamp(start, end);
+                    span_start - start..m.end.clamp(span_start, end) - start
+                })
+                .collect();
+            // anything still left from before this line runs on into it, or it'd have been
+            // skipped above
+            let continued = matches.get(first).is_some_and(|m| m.start < start);
+
+            let keep_going = sink(Line {
+                text: &text[start..end],
+                line_number,
+                byte_offset,
+                spans,
+                continued,
+            })?;
+            if !keep_going {
+                return Ok(());
+            }
+
+            let raw = &bytes[byte_offset..];
+            byte_offset += memchr::memchr(b'\n', raw).map_or(raw.len(), |i| i + 1);
+            start = next;
+        }
+
+        Ok(())
+    }
+
+    // finds the matches in `bytes` (a line, possibly with its line ending) and hands it to `sink`
+    fn line<S>(
+        &self,
+        bytes: &[u8],
+        line_number: usize,
+        byte_offset: usize,
+        sink: &mut S,
+    ) -> io::Result<bool>
+    where
+        S: FnMut(Line) -> io::Result<bool>,
+    {
+        // only copied if it isn't valid UTF-8
+        let text = String::from_utf8_lossy(trim_line_ending(bytes));
+
+        sink(Line {
+            spans: self.matcher.find_all(&text),
+            text: &text,
+            line_number,
+            byte_offset,
+            continued: false,
+        })
+    }
+}
+
+// takes the "\n" or "\r\n" off the end of `line`, if it has one
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(rest) => rest.strip_suffix(b"\r").unwrap_or(rest),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::Literal;
+
+    // a matcher that never finds anything
+    struct Nothing;
+
+    impl Matcher for Nothing {
+        fn find_at(&self, _: &str, _: usize) -> Option<Range<usize>> {
+            None
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let contents: &[u8] = b"Rust:\r\nsafe, \xff fast\nPick three.";
+
+        let mut lines = Vec::new();
+        Searcher::new(Literal::new("fast"))
+            .search_lines(contents, |line| {
+                lines.push((
+                    line.text.to_string(),
+                    line.line_number,
+                    line.byte_offset,
+                    line.spans.len(),
+                ));
+                Ok(true)
+            })
+            .unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                (String::from("Rust:"), 1, 0, 0),
+                (String::from("safe, \u{fffd} fast"), 2, 7, 1),
+                (String::from("Pick three."), 3, 20, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn slices_like_readers() {
+        let searcher = Searcher::new(Literal::new("a"));
+        let inputs: [&[u8]; 4] = [b"a\r\nb\n\xffa\n\nlast a", b"", b"\n", b"a\n"];
+        for contents in inputs {
+            let mut from_reader = Vec::new();
+            searcher
+                .search_lines(contents, |line| {
+                    from_reader.push(format!("{:?}", line));
+                    Ok(true)
+                })
+                .unwrap();
+            let mut from_slice = Vec::new();
+            searcher
+                .search_slice_lines(contents, |line| {
+                    from_slice.push(format!("{:?}", line));
+                    Ok(true)
+                })
+                .unwrap();
+
+            assert_eq!(from_slice, from_reader, "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn slices_skip_to_matches() {
+        let contents: &[u8] = b"ab\r\nb\n\xffab a\n\nabab\r\nlast ab\na\r\n";
+        for query in ["ab", "a", "b\r", "\u{fffd}", "", "nothing"] {
+            let searcher = Searcher::new(Literal::new(query));
+            let mut every_line = Vec::new();
+            searcher
+                .search(contents, |line| {
+                    every_line.push(format!("{:?}", line));
+                    Ok(true)
+                })
+                .unwrap();
+            let mut skipping = Vec::new();
+            searcher
+                .search_slice(contents, |line| {
+                    skipping.push(format!("{:?}", line));
+                    Ok(true)
+                })
+                .unwrap();
+
+            assert_eq!(skipping, every_line, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn multiline() {
+        let contents: &[u8] = b"one\r\ntw\xff two\nthree\n\nfour";
+
+        let mut lines = Vec::new();
+        Searcher::new(Literal::new("two\nthree\n\nfo"))
+            .multiline(true)
+            .search(contents, |line| {
+                let matched: Vec<&str> = line.spans.iter().map(|s| &line.text[s.clone()]).collect();
+                let continued = line.continued;
+                lines.push((
+                    line.line_number,
+                    line.byte_offset,
+                    matched.join("|"),
+                    continued,
+                ));
+                Ok(true)
+            })
+            .unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                (2, 5, String::from("two"), false),
+                (3, 13, String::from("three"), true),
+                (4, 19, String::new(), true),
+                (5, 20, String::from("fo"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_like_line_by_line() {
+        // a match without a line break in it is on the same line either way
+        let inputs: [&[u8]; 5] = [b"a\r\nb\n\xffa\n\nlast a", b"", b"\n", b"a\n", b"ba"];
+        for query in ["a", ""] {
+            let line_by_line = Searcher::new(Literal::new(query));
+            let multiline = Searcher::new(Literal::new(query)).multiline(true);
+            for contents in inputs {
+                let mut expected = Vec::new();
+                line_by_line
+                    .search_lines(contents, |line| {
+                        expected.push(format!("{:?}", line));
+                        Ok(true)
+                    })
+                    .unwrap();
+                let mut found = Vec::new();
+                multiline
+                    .search_lines(contents, |line| {
+                        found.push(format!("{:?}", line));
+                        Ok(true)
+                    })
+                    .unwrap();
+
+                assert_eq!(found, expected, "{:?} in {:?}", query, contents);
+            }
+        }
+    }
+
+    #[test]
+    fn only_matches() {
+        let mut found = Vec::new();
+        Searcher::new(Literal::new("a"))
+            .search(&b"a\nb\ncaa\n"[..], |line| {
+                found.push((line.line_number, line.text.to_string(), line.spans.len()));
+                Ok(true)
+            })
+            .unwrap();
+
+        assert_eq!(
+            found,
+            vec![(1, String::from("a"), 1), (3, String::from("caa"), 2)]
+        );
+    }
+
+    #[test]
+    fn stops_early() {
+        let mut seen = 0;
+        Searcher::new(Nothing)
+            .search_lines(&b"a\nb\nc\n"[..], |_| {
+                seen += 1;
+                Ok(seen < 2)
+            })
+            .unwrap();
+
+        assert_eq!(seen, 2);
+    }
+}
@@ -0,0 +1,73 @@
+// finds a fixed string in a buffer with the Boyer-Moore-Horspool algorithm. only the last byte of
+// the needle is compared at each spot at first, and whatever byte is there says how far the needle
+// can be slid along before it could possibly match, which for most text is nearly the whole length
+// of the needle. the longer the needle, the fewer bytes of the haystack get looked at
+pub struct Horspool<'a> {
+    needle: &'a [u8],
+    // how far to slide the needle when the byte under its last byte is the index
+    skip: [usize; 256],
+}
+
+impl<'a> Horspool<'a> {
+    pub fn new(needle: &'a [u8]) -> Horspool<'a> {
+        let mut skip = [needle.len(); 256];
+        if let Some((_, init)) = needle.split_last() {
+            for (i, &b) in init.iter().enumerate() {
+                skip[b as usize] = init.len() - i;
+            }
+        }
+        Horspool { needle, skip }
+    }
+
+    // finds the first place at or after `start` in `haystack` where the needle is
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        let (&last, init) = match self.needle.split_last() {
+            Some(split) => split,
+            None => return (start <= haystack.len()).then_some(start),
+        };
+        // a single byte can't be skipped past any faster than memchr already does
+        if init.is_empty() {
+            return memchr::memchr(last, haystack.get(start..)?).map(|i| start + i);
+        }
+
+        let mut i = start;
+        while i + self.needle.len() <= haystack.len() {
+            let b = haystack[i + init.len()];
+            if b == last && haystack[i..i + init.len()] == *init {
+                return Some(i);
+            }
+            i += self.skip[b as usize];
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_occurrence() {
+        let haystack = b"abracadabra, abracadabra";
+        let needle = Horspool::new(b"abra");
+
+        let mut found = Vec::new();
+        let mut start = 0;
+        while let Some(i) = needle.find_at(haystack, start) {
+            found.push(i);
+            start = i + 1;
+        }
+        assert_eq!(found, vec![0, 7, 13, 20]);
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert_eq!(Horspool::new(b"").find_at(b"abc", 1), Some(1));
+        assert_eq!(Horspool::new(b"").find_at(b"abc", 4), None);
+        assert_eq!(Horspool::new(b"c").find_at(b"abc", 1), Some(2));
+        assert_eq!(Horspool::new(b"c").find_at(b"abc", 4), None);
+        assert_eq!(Horspool::new(b"abcd").find_at(b"abc", 0), None);
+        assert_eq!(Horspool::new(b"bc").find_at(b"abc", 0), Some(1));
+        assert_eq!(Horspool::new(b"aab").find_at(b"aaaab", 0), Some(2));
+    }
+}
@@ -0,0 +1,376 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use crate::error::Error;
+
+// the path that stands for standard input
+pub const STDIN: &str = "-";
+
+// the files in a directory that ignore patterns are read from, in the same format as .gitignore
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+// decides which of the files found while walking a directory get searched. files named on the
+// command line are always searched
+pub struct Filter {
+    // only files matching one of these are searched (`--include`)
+    pub include: Option<GlobSet>,
+    // files and directories matching one of these are skipped (`--exclude`)
+    pub exclude: Option<GlobSet>,
+    // whether to look at files and directories whose names start with a `.` (`--hidden`)
+    pub hidden: bool,
+    // whether to skip what .gitignore and .ignore files say to, along with `.git` itself
+    // (turned off with `--no-ignore`)
+    pub ignore_files: bool,
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter {
+            include: None,
+            exclude: None,
+            hidden: false,
+            ignore_files: true,
+        }
+    }
+}
+
+impl Filter {
+    // whether `path` (named `name`) should be searched, or walked if it's a directory
+    fn allows(&self, path: &Path, name: &str, is_dir: bool, ignores: &[Gitignore]) -> bool {
+        if !self.hidden && name.starts_with('.') {
+            return false;
+        }
+        if self.ignore_files && (is_dir && name == ".git" || is_ignored(path, is_dir, ignores)) {
+            return false;
+        }
+        if self
+            .exclude
+            .as_ref()
+            .is_some_and(|set| matches(set, path, name))
+        {
+            return false;
+        }
+        is_dir
+            || self
+                .include
+                .as_ref()
+                .is_none_or(|set| matches(set, path, name))
+    }
+}
+
+// compiles `globs` into one set, or gives back None if there aren't any
+pub fn glob_set(globs: &[String]) -> Result<Option<GlobSet>, Error> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|source| Error::Glob {
+            glob: glob.clone(),
+            source,
+        })?);
+    }
+    builder.build().map(Some).map_err(|source| Error::Glob {
+        glob: globs.join(" "),
+        source,
+    })
+}
+
+// a glob can either be about the name of a file (`*.rs`) or where it is (`src/**/*.rs`)
+fn matches(set: &GlobSet, path: &Path, name: &str) -> bool {
+    set.is_match(name) || set.is_match(path)
+}
+
+// checks `path` against the ignore files found on the way down to it. the closest one that has
+// anything to say about it wins, so a `!pattern` deeper down can bring back what a parent ignored
+fn is_ignored(path: &Path, is_dir: bool, ignores: &[Gitignore]) -> bool {
+    for ignore in ignores.iter().rev() {
+        match ignore.matched(path, is_dir) {
+            Match::None => continue,
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+        }
+    }
+    false
+}
+
+// reads the ignore files in `dir`, if it has any
+fn read_ignore_files(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in IGNORE_FILES {
+        let path = dir.join(name);
+        if path.is_file() {
+            found = true;
+            if let Some(e) = builder.add(&path) {
+                eprintln!("minigrep: {}", e);
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    match builder.build() {
+        Ok(ignore) => Some(ignore),
+        Err(e) => {
+            eprintln!("minigrep: {}", e);
+            None
+        }
+    }
+}
+
+// expands `paths` into the list of files to search. plain files are kept as they are and
+// directories are walked recursively, in sorted order so the output is the same from run to run,
+// skipping whatever `filter` says to. `STDIN` is passed through untouched
+//
+// like grep, a path that can't be read is reported and skipped so the rest still get searched.
+// along with the files, this gives back how many paths were skipped that way
+//
+// only the ignore files inside the directories being walked are read, not ones further up
+pub fn files(paths: &[String], filter: &Filter) -> (Vec<PathBuf>, usize) {
+    let mut files = Vec::new();
+    let mut failed = 0;
+
+    for path in paths {
+        if path == STDIN {
+            files.push(PathBuf::from(STDIN));
+            continue;
+        }
+
+        let path = Path::new(path);
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("minigrep: {}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            walk(path, filter, &mut Vec::new(), &mut files, &mut failed);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    (files, failed)
+}
+
+// pushes every file under `dir` that `filter` allows onto `files`. a directory we can't read
+// along the way is reported, counted in `failed` and skipped rather than ending the whole search.
+// symlinks are skipped too, so a link pointing back up the tree can't send us around in circles
+//
+// `ignores` has the ignore files from every directory above `dir`
+fn walk(
+    dir: &Path,
+    filter: &Filter,
+    ignores: &mut Vec<Gitignore>,
+    files: &mut Vec<PathBuf>,
+    failed: &mut usize,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("minigrep: {}: {}", dir.display(), e);
+            *failed += 1;
+            return;
+        }
+    };
+
+    let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let ignore = if filter.ignore_files {
+        read_ignore_files(dir)
+    } else {
+        None
+    };
+    let pushed = ignore.is_some();
+    ignores.extend(ignore);
+
+    for entry in entries {
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !filter.allows(&path, &name, file_type.is_dir(), ignores) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            walk(&path, filter, ignores, files, failed);
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+
+    if pushed {
+        ignores.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // walks `root` and gives back what was found relative to it
+    fn relative_files(root: &Path, filter: &Filter) -> Vec<PathBuf> {
+        let (found, failed) = files(&[root.to_str().unwrap().to_string()], filter);
+        assert_eq!(failed, 0);
+        found
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn walks_directories_in_order() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("b/inner")).unwrap();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("b/inner/deep.txt"), "deep").unwrap();
+        fs::write(root.join("b/file.txt"), "b").unwrap();
+        fs::write(root.join("a/file.txt"), "a").unwrap();
+        fs::write(root.join("top.txt"), "top").unwrap();
+
+        let found = relative_files(root, &Filter::default());
+
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("a/file.txt"),
+                PathBuf::from("b/file.txt"),
+                PathBuf::from("b/inner/deep.txt"),
+                PathBuf::from("top.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src/generated")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref").unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join(".hidden.rs"), "hidden").unwrap();
+        fs::write(root.join("target/debug/out.rs"), "out").unwrap();
+        fs::write(root.join("build.log"), "log").unwrap();
+        fs::write(root.join("src/main.rs"), "main").unwrap();
+        fs::write(root.join("src/notes.txt"), "notes").unwrap();
+        fs::write(root.join("src/generated/.ignore"), "*.rs\n!keep.rs\n").unwrap();
+        fs::write(root.join("src/generated/gen.rs"), "gen").unwrap();
+        fs::write(root.join("src/generated/keep.rs"), "keep").unwrap();
+
+        let default = relative_files(root, &Filter::default());
+        let hidden = relative_files(
+            root,
+            &Filter {
+                hidden: true,
+                ..Filter::default()
+            },
+        );
+        let no_ignore = relative_files(
+            root,
+            &Filter {
+                ignore_files: false,
+                ..Filter::default()
+            },
+        );
+        let globs = relative_files(
+            root,
+            &Filter {
+                include: glob_set(&["*.rs".to_string()]).unwrap(),
+                exclude: glob_set(&["generated".to_string()]).unwrap(),
+                ..Filter::default()
+            },
+        );
+
+        assert_eq!(
+            default,
+            vec![
+                PathBuf::from("src/generated/keep.rs"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/notes.txt"),
+            ]
+        );
+        assert_eq!(
+            hidden,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from(".hidden.rs"),
+                PathBuf::from("src/generated/.ignore"),
+                PathBuf::from("src/generated/keep.rs"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/notes.txt"),
+            ]
+        );
+        assert_eq!(
+            no_ignore,
+            vec![
+                PathBuf::from("build.log"),
+                PathBuf::from("src/generated/gen.rs"),
+                PathBuf::from("src/generated/keep.rs"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/notes.txt"),
+                PathBuf::from("target/debug/out.rs"),
+            ]
+        );
+        assert_eq!(globs, vec![PathBuf::from("src/main.rs")]);
+    }
+
+    #[test]
+    fn named_files_are_always_searched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let path = root.join(".hidden.txt");
+        fs::write(&path, "hidden").unwrap();
+
+        let filter = Filter {
+            exclude: glob_set(&["*.txt".to_string()]).unwrap(),
+            ..Filter::default()
+        };
+        let (found, _) = files(&[path.to_str().unwrap().to_string()], &filter);
+
+        assert_eq!(found, vec![path]);
+    }
+
+    #[test]
+    fn bad_glob() {
+        let err = glob_set(&["*.rs".to_string(), "a[".to_string()]).unwrap_err();
+        assert!(matches!(&err, Error::Glob { glob, .. } if glob == "a["));
+        assert!(err.to_string().starts_with("invalid glob `a[`: "));
+        assert!(glob_set(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let path = root.join("a.txt");
+        fs::write(&path, "duct").unwrap();
+
+        // the paths around the missing one are still searched
+        let path = path.to_str().unwrap().to_string();
+        let (found, failed) = files(
+            &[path.clone(), String::from("does/not/exist"), path.clone()],
+            &Filter::default(),
+        );
+
+        assert_eq!(found, vec![PathBuf::from(&path), PathBuf::from(&path)]);
+        assert_eq!(failed, 1);
+    }
+}
@@ -16,6 +16,16 @@ pub struct ThreadPool {
 
 impl ThreadPool {
     pub fn new(n: usize) -> ThreadPool {
+        ThreadPool::build(n, true)
+    }
+
+    // same as `new`, but the workers don't print what they're up to. for when the pool is used by
+    // something that has its own output to worry about
+    pub fn quiet(n: usize) -> ThreadPool {
+        ThreadPool::build(n, false)
+    }
+
+    fn build(n: usize, logging: bool) -> ThreadPool {
         assert!(n > 0);
 
         let mut workers = VecDeque::with_capacity(n);
@@ -23,7 +33,7 @@ impl ThreadPool {
         let receiver = Arc::new(Mutex::new(receiver));
 
         for id in 0..n {
-            workers.push_back(Worker::new(id, Arc::clone(&receiver)));
+            workers.push_back(Worker::new(id, Arc::clone(&receiver), logging));
         }
 
         ThreadPool { workers, sender }
@@ -55,21 +65,27 @@ impl Drop for ThreadPool {
 }
 
 struct Worker {
+    // nothing reads this, but it tells workers apart when looking at one in a debugger
+    #[allow(dead_code)]
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, logging: bool) -> Worker {
         let thread = thread::spawn(move || loop {
             let msg = receiver.lock().unwrap().recv().unwrap();
             match msg {
                 Message::Terminate => {
-                    println!("terminating worker {}", id);
+                    if logging {
+                        println!("terminating worker {}", id);
+                    }
                     break;
                 }
                 Message::NewJob(job) => {
-                    println!("running job on worker {}", id);
+                    if logging {
+                        println!("running job on worker {}", id);
+                    }
                     job();
                 }
             }
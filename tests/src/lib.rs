@@ -157,9 +157,41 @@ hiya first line
 hiya second line
 third line";
 
-        assert_eq!(
-            search(query, contents),
-            vec!["hiya first line", "hiya second line"]
-        );
+        let lines: Vec<&str> = search(query, contents).iter().map(|m| m.line).collect();
+        assert_eq!(lines, vec!["hiya first line", "hiya second line"]);
+    }
+
+    // other crates can plug their own matchers into minigrep's `Searcher`
+    #[test]
+    fn test_workspace_matcher() {
+        use minigrep::{Matcher, Searcher};
+        use std::ops::Range;
+
+        // matches any run of digits
+        struct Digits;
+
+        impl Matcher for Digits {
+            fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+                let begin = start + line[start..].find(|c: char| c.is_ascii_digit())?;
+                let len = line[begin..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(line.len() - begin);
+                Some(begin..begin + len)
+            }
+        }
+
+        let contents = "\
+hiya 1st line
+hiya second line
+3rd line, 42 words";
+
+        let mut found = Vec::new();
+        Searcher::new(Digits)
+            .search(contents.as_bytes(), |line| {
+                found.push((line.line_number, line.spans.len()));
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(found, vec![(1, 1), (3, 2)]);
     }
 }